@@ -0,0 +1,244 @@
+use crate::{
+    ByteSink, ByteSource, CONTROLLER_MESSAGE_ID_EMERGENCY_STOP, ControllerMessage, MAX_FRAME_LENGTH, MESSAGE_START_BIT,
+    RejectReason, RemoteMessage, SerialBuffer, controller_message_length, drop_interrupted_frame, is_emergency_stop_id,
+    remote_message_length, take_unmarked_emergency_stop,
+};
+
+/// Frames sent to this address are accepted by every remote on the bus
+pub const BROADCAST_ADDRESS: u8 = 0x7F;
+
+// On a shared bus every frame is preceded by a two byte address header: the start byte with this
+// id, then the address of the remote the frame is for or comes from.
+const ADDRESS_HEADER_ID: u8 = 0x70;
+const ADDRESS_HEADER_LENGTH: usize = 2;
+
+fn skip_to_start_byte(rx_buffer: &mut impl ByteSource) -> Option<u8> {
+    while let Some(id_byte) = rx_buffer.peek() {
+        if (id_byte & MESSAGE_START_BIT) != 0 {
+            return Some(id_byte & !MESSAGE_START_BIT);
+        }
+        rx_buffer.pop();
+    }
+    None
+}
+
+// Pops the address header at the front of `rx_buffer` once the frame behind it is complete, so the
+// header and its frame are always consumed together.
+fn take_address_header(rx_buffer: &mut impl ByteSource, frame_length: impl Fn(u8) -> Option<usize>) -> Result<Option<u8>, ()> {
+    if drop_interrupted_frame(rx_buffer, ADDRESS_HEADER_LENGTH) {
+        return Err(());
+    }
+    let Some(id_byte) = rx_buffer.peek_at(ADDRESS_HEADER_LENGTH) else {
+        return Ok(None);
+    };
+    if (id_byte & MESSAGE_START_BIT) == 0 {
+        rx_buffer.pop();
+        return Err(());
+    }
+    if let Some(length) = frame_length(id_byte & !MESSAGE_START_BIT) && rx_buffer.count() < ADDRESS_HEADER_LENGTH + length {
+        return Ok(None);
+    }
+    rx_buffer.pop();
+    Ok(rx_buffer.pop())
+}
+
+fn push_addressed<const M: usize>(address: u8, frame: &mut SerialBuffer<M>, tx_buffer: &mut impl ByteSink) -> bool {
+    if tx_buffer.free_space() < ADDRESS_HEADER_LENGTH + frame.count() {
+        return false;
+    }
+    tx_buffer.push(ADDRESS_HEADER_ID | MESSAGE_START_BIT);
+    tx_buffer.push(address & 0x7F);
+    while let Some(byte) = frame.pop() {
+        tx_buffer.push(byte);
+    }
+    true
+}
+
+impl ControllerMessage {
+    /// Sends the message to the remote at `address`, or to every remote with `BROADCAST_ADDRESS`
+    pub fn try_send_to(&self, address: u8, tx_buffer: &mut impl ByteSink) -> bool {
+        let mut frame = SerialBuffer::<MAX_FRAME_LENGTH>::new();
+        self.try_send(&mut frame) && push_addressed(address, &mut frame, tx_buffer)
+    }
+}
+
+impl RemoteMessage {
+    /// Receives the next addressed message along with the address of the remote that sent it.
+    /// Frames without an address header are discarded with an error.
+    #[allow(clippy::result_unit_err)]
+    pub fn try_receive_from(rx_buffer: &mut impl ByteSource) -> Result<Option<(u8, Self)>, ()> {
+        let Some(id) = skip_to_start_byte(rx_buffer) else {
+            return Ok(None);
+        };
+        if id != ADDRESS_HEADER_ID {
+            return match Self::try_receive(rx_buffer)? {
+                Some(..) => Err(()),
+                None => Ok(None),
+            };
+        }
+        match take_address_header(rx_buffer, remote_message_length)? {
+            Some(address) => Ok(Self::try_receive(rx_buffer)?.map(|message| (address, message))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Remote side of a shared bus. Only passes on messages addressed to this remote or broadcast,
+/// and prefixes everything it sends with this remote's address.
+///
+/// An `EmergencyStop` without an address header is taken as a broadcast, so a single frame stops
+/// every coil on the bus. Like any broadcast it isn't answered; an `EmergencyStop` addressed to this
+/// remote is, which is how `BusEmergencyStopSender` collects an `EmergencyStopAck` from each remote.
+pub struct AddressFilter {
+    address: u8,
+}
+
+impl AddressFilter {
+    pub fn new(address: u8) -> Self {
+        Self {
+            address: address & 0x7F,
+        }
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    pub fn try_send(&self, message: &RemoteMessage, tx_buffer: &mut impl ByteSink) -> bool {
+        let mut frame = SerialBuffer::<MAX_FRAME_LENGTH>::new();
+        message.try_send(&mut frame) && push_addressed(self.address, &mut frame, tx_buffer)
+    }
+
+    /// Receives the next message for this remote, along with whether it was broadcast. Nothing
+    /// should be sent in reply to a broadcast, since every remote would answer at once.
+    #[allow(clippy::result_unit_err)]
+    pub fn try_receive(&self, rx_buffer: &mut impl ByteSource) -> Result<Option<(ControllerMessage, bool)>, ()> {
+        self.try_receive_or_reject(rx_buffer).map_err(|_| ())
+    }
+
+    // Rejections also say whether the frame was broadcast, so nobody answers a broadcast one
+    pub(crate) fn try_receive_or_reject(&self, rx_buffer: &mut impl ByteSource) -> Result<Option<(ControllerMessage, bool)>, (u8, RejectReason, bool)> {
+        loop {
+            let id = match rx_buffer.peek() {
+                Some(id_byte) if (id_byte & MESSAGE_START_BIT) != 0 => id_byte & !MESSAGE_START_BIT,
+                Some(_) => {
+                    match take_unmarked_emergency_stop(rx_buffer) {
+                        Some(true) => return Ok(Some((ControllerMessage::EmergencyStop, true))),
+                        Some(false) => rx_buffer.pop(),
+                        None => return Ok(None),
+                    };
+                    continue;
+                },
+                None => return Ok(None),
+            };
+            if id != ADDRESS_HEADER_ID {
+                match ControllerMessage::try_receive_or_reject(rx_buffer) {
+                    Ok(Some(ControllerMessage::EmergencyStop)) => return Ok(Some((ControllerMessage::EmergencyStop, true))),
+                    Ok(None) => return Ok(None),
+                    _ => continue,
+                }
+            }
+            let frame_length = |id| {
+                controller_message_length(if is_emergency_stop_id(id) { CONTROLLER_MESSAGE_ID_EMERGENCY_STOP } else { id })
+            };
+            let address = match take_address_header(rx_buffer, frame_length) {
+                Ok(Some(address)) => address,
+                Ok(None) => return Ok(None),
+                Err(..) => continue,
+            };
+            let broadcast = address == BROADCAST_ADDRESS;
+            if address != self.address && !broadcast {
+                if let Ok(None) = ControllerMessage::try_receive_or_reject(rx_buffer) {
+                    return Ok(None);
+                }
+                continue;
+            }
+            return ControllerMessage::try_receive_or_reject(rx_buffer)
+                .map(|message| message.map(|message| (message, broadcast)))
+                .map_err(|(message_id, reason)| (message_id, reason, broadcast));
+        }
+    }
+}
+
+/// Controller side `EmergencyStopSender` for a shared bus. Once triggered, sends one unaddressed
+/// `EmergencyStop` so every coil stops at once, then keeps sending an addressed `EmergencyStop`
+/// every `repeat_interval_ms` to each remote in `addresses` that hasn't answered with an
+/// `EmergencyStopAck` yet. Pass it every reply from `RemoteMessage::try_receive_from`.
+pub struct BusEmergencyStopSender<const REMOTES: usize> {
+    addresses: [u8; REMOTES],
+    repeat_interval_ms: u32,
+    acked: [bool; REMOTES],
+    active: bool,
+    broadcast_sent: bool,
+    last_sent_ms: Option<u32>,
+}
+
+impl<const REMOTES: usize> BusEmergencyStopSender<REMOTES> {
+    pub fn new(addresses: [u8; REMOTES], repeat_interval_ms: u32) -> Self {
+        Self {
+            addresses,
+            repeat_interval_ms,
+            acked: [false; REMOTES],
+            active: false,
+            broadcast_sent: false,
+            last_sent_ms: None,
+        }
+    }
+
+    pub fn trigger(&mut self) {
+        self.acked = [false; REMOTES];
+        self.active = true;
+        self.broadcast_sent = false;
+        self.last_sent_ms = None;
+    }
+
+    /// Some remote hasn't acknowledged the stop yet
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn is_acked(&self, address: u8) -> bool {
+        self.addresses.iter().zip(self.acked).any(|(remote, acked)| *remote == address && acked)
+    }
+
+    /// Sends whatever is due, returning whether anything was sent. Addressed frames that don't fit
+    /// in `tx_buffer` are sent on the next repeat.
+    pub fn poll(&mut self, now_ms: u32, tx_buffer: &mut impl ByteSink) -> bool {
+        if !self.active {
+            return false;
+        }
+        if !self.broadcast_sent {
+            self.broadcast_sent = ControllerMessage::EmergencyStop.try_send(tx_buffer);
+            return self.broadcast_sent;
+        }
+        let due = match self.last_sent_ms {
+            Some(last_sent_ms) => now_ms.wrapping_sub(last_sent_ms) >= self.repeat_interval_ms,
+            None => true,
+        };
+        if !due {
+            return false;
+        }
+        let mut sent = false;
+        for (address, acked) in self.addresses.iter().zip(self.acked) {
+            if !acked {
+                sent |= ControllerMessage::EmergencyStop.try_send_to(*address, tx_buffer);
+            }
+        }
+        if sent {
+            self.last_sent_ms = Some(now_ms);
+        }
+        sent
+    }
+
+    pub fn handle(&mut self, address: u8, message: &RemoteMessage) -> bool {
+        if !matches!(message, RemoteMessage::EmergencyStopAck) {
+            return false;
+        }
+        let Some(index) = self.addresses.iter().position(|remote| *remote == address) else {
+            return false;
+        };
+        self.acked[index] = true;
+        self.active = !self.acked.iter().all(|acked| *acked);
+        true
+    }
+}
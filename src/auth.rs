@@ -0,0 +1,251 @@
+use core::hash::Hasher;
+
+use siphasher::sip::SipHasher24;
+
+use crate::{
+    ByteSink, ByteSource, ControllerMessage, MESSAGE_START_BIT, RejectReason, SerialBuffer,
+    controller_message_length, drop_interrupted_frame, is_emergency_stop_id, take_unmarked_emergency_stop,
+};
+
+const CONTROLLER_MESSAGE_ID_AUTHENTICATED: u8 = 15;
+
+const COUNTER_LENGTH: usize = 5;
+const TAG_LENGTH: usize = 5;
+const MAX_INNER_LENGTH: usize = 16;
+const HEADER_LENGTH: usize = 1 + COUNTER_LENGTH;
+const MAX_FRAME_LENGTH: usize = HEADER_LENGTH + MAX_INNER_LENGTH + TAG_LENGTH;
+
+/// Authenticated command frames for links where anyone could transmit.
+///
+/// Each command is wrapped as: start byte, a 32 bit frame counter, the wrapped message with its
+/// start bit cleared, then a 35 bit SipHash-2-4 tag over everything before it, all in 7 bit
+/// bytes. The remote only accepts frames with a valid tag and a counter higher than the last one
+/// it accepted, so recorded frames can't be replayed. Both counters should be persisted across
+/// resets with `set_tx_counter`/`set_rx_counter`, otherwise a reset remote accepts old frames
+/// again and a reset controller is refused until its counter catches up.
+///
+/// Plain frames are refused on the receiving side, except `EmergencyStop` which is always honoured.
+pub struct Authenticator {
+    key: [u8; 16],
+    tx_counter: u32,
+    rx_counter: u32,
+}
+
+impl Authenticator {
+    pub fn new(key: [u8; 16]) -> Self {
+        Self {
+            key,
+            tx_counter: 0,
+            rx_counter: 0,
+        }
+    }
+
+    pub fn tx_counter(&self) -> u32 {
+        self.tx_counter
+    }
+
+    pub fn set_tx_counter(&mut self, counter: u32) {
+        self.tx_counter = counter;
+    }
+
+    pub fn rx_counter(&self) -> u32 {
+        self.rx_counter
+    }
+
+    pub fn set_rx_counter(&mut self, counter: u32) {
+        self.rx_counter = counter;
+    }
+
+    fn tag(&self, authenticated_bytes: &[u8]) -> u64 {
+        let mut hasher = SipHasher24::new_with_key(&self.key);
+        hasher.write(authenticated_bytes);
+        hasher.finish()
+    }
+
+    #[allow(clippy::identity_op)]
+    pub fn try_send(&mut self, message: &ControllerMessage, tx_buffer: &mut impl ByteSink) -> bool {
+        let mut inner = SerialBuffer::<MAX_INNER_LENGTH>::new();
+        if !message.try_send(&mut inner) {
+            return false;
+        }
+        let length = HEADER_LENGTH + inner.count() + TAG_LENGTH;
+        if tx_buffer.free_space() < length {
+            return false;
+        }
+        let counter = self.tx_counter.wrapping_add(1);
+        let mut frame = [0u8; MAX_FRAME_LENGTH];
+        frame[0] = CONTROLLER_MESSAGE_ID_AUTHENTICATED | MESSAGE_START_BIT;
+        frame[1] = ((counter >>  0) & 0x7F) as u8;
+        frame[2] = ((counter >>  7) & 0x7F) as u8;
+        frame[3] = ((counter >> 14) & 0x7F) as u8;
+        frame[4] = ((counter >> 21) & 0x7F) as u8;
+        frame[5] = ((counter >> 28) & 0x7F) as u8;
+        let mut i = HEADER_LENGTH;
+        while let Some(byte) = inner.pop() {
+            frame[i] = byte & !MESSAGE_START_BIT;
+            i += 1;
+        }
+        let tag = self.tag(&frame[..i]);
+        for shift in [0, 7, 14, 21, 28] {
+            frame[i] = ((tag >> shift) & 0x7F) as u8;
+            i += 1;
+        }
+        for byte in &frame[..length] {
+            tx_buffer.push(*byte);
+        }
+        self.tx_counter = counter;
+        true
+    }
+
+    #[allow(clippy::result_unit_err)]
+    pub fn try_receive(&mut self, rx_buffer: &mut impl ByteSource) -> Result<Option<ControllerMessage>, ()> {
+        self.try_receive_or_reject(rx_buffer).map_err(|_| ())
+    }
+
+    #[allow(clippy::identity_op)]
+    pub(crate) fn try_receive_or_reject(&mut self, rx_buffer: &mut impl ByteSource) -> Result<Option<ControllerMessage>, (u8, RejectReason)> {
+        while let Some(id_byte) = rx_buffer.peek() {
+            if (id_byte & MESSAGE_START_BIT) != 0 {
+                break;
+            }
+            match take_unmarked_emergency_stop(rx_buffer) {
+                Some(true) => return Ok(Some(ControllerMessage::EmergencyStop)),
+                Some(false) => {},
+                None => return Ok(None),
+            }
+            rx_buffer.pop();
+        }
+        let Some(id) = rx_buffer.peek() else {
+            return Ok(None);
+        };
+        let id = id & !MESSAGE_START_BIT;
+        if is_emergency_stop_id(id) {
+            return ControllerMessage::try_receive_or_reject(rx_buffer);
+        }
+        if id != CONTROLLER_MESSAGE_ID_AUTHENTICATED {
+            return match ControllerMessage::try_receive_or_reject(rx_buffer)? {
+                Some(message) => Err((message.message_id(), RejectReason::NotAuthenticated)),
+                None => Ok(None),
+            };
+        }
+        if drop_interrupted_frame(rx_buffer, HEADER_LENGTH + 1) {
+            return Err((id, RejectReason::Malformed));
+        }
+        let Some(inner_id) = rx_buffer.peek_at(HEADER_LENGTH) else {
+            return Ok(None);
+        };
+        let inner_length = match controller_message_length(inner_id) {
+            Some(inner_length) if inner_length <= MAX_INNER_LENGTH => inner_length,
+            _ => {
+                rx_buffer.pop();
+                return Err((inner_id, RejectReason::UnknownMessage));
+            },
+        };
+        let length = HEADER_LENGTH + inner_length + TAG_LENGTH;
+        if drop_interrupted_frame(rx_buffer, length) {
+            return Err((inner_id, RejectReason::Malformed));
+        }
+        if rx_buffer.count() < length {
+            return Ok(None);
+        }
+        let mut frame = [0u8; MAX_FRAME_LENGTH];
+        for byte in &mut frame[..length] {
+            *byte = rx_buffer.pop().unwrap();
+        }
+        let tag_start = HEADER_LENGTH + inner_length;
+        let tag = frame[tag_start..length].iter().enumerate()
+            .fold(0u64, |tag, (i, byte)| tag | ((*byte as u64) << (7 * i)));
+        if tag != self.tag(&frame[..tag_start]) & 0x7_FFFF_FFFF {
+            return Err((inner_id, RejectReason::NotAuthenticated));
+        }
+        let counter =
+            ((frame[1] as u32) <<  0) |
+            ((frame[2] as u32) <<  7) |
+            ((frame[3] as u32) << 14) |
+            ((frame[4] as u32) << 21) |
+            ((frame[5] as u32) << 28);
+        if counter <= self.rx_counter {
+            return Err((inner_id, RejectReason::NotAuthenticated));
+        }
+        self.rx_counter = counter;
+        let mut inner = SerialBuffer::<MAX_INNER_LENGTH>::new();
+        inner.push(frame[HEADER_LENGTH] | MESSAGE_START_BIT);
+        for byte in &frame[HEADER_LENGTH + 1..tag_start] {
+            inner.push(*byte);
+        }
+        match ControllerMessage::try_receive_or_reject(&mut inner)? {
+            Some(message) => Ok(Some(message)),
+            None => Err((inner_id, RejectReason::Malformed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 16] = *b"0123456789abcdef";
+
+    // Sends `message` and returns a copy of the frame for replaying
+    fn send(controller: &mut Authenticator, message: &ControllerMessage, link: &mut SerialBuffer<64>) -> ([u8; MAX_FRAME_LENGTH], usize) {
+        assert!(controller.try_send(message, link));
+        let mut frame = [0u8; MAX_FRAME_LENGTH];
+        let (first, second) = link.as_contiguous_slices();
+        let length = first.len() + second.len();
+        frame[..first.len()].copy_from_slice(first);
+        frame[first.len()..length].copy_from_slice(second);
+        (frame, length)
+    }
+
+    #[test]
+    fn authenticated_frames_round_trip() {
+        let mut controller = Authenticator::new(KEY);
+        let mut remote = Authenticator::new(KEY);
+        let mut link = SerialBuffer::<64>::new();
+        for sequence in [1, 0x0FFF_FFFF] {
+            send(&mut controller, &ControllerMessage::Ping(sequence), &mut link);
+            assert!(matches!(remote.try_receive(&mut link), Ok(Some(ControllerMessage::Ping(echo))) if echo == sequence));
+        }
+        assert_eq!(remote.rx_counter(), controller.tx_counter());
+        assert_eq!(link.count(), 0);
+    }
+
+    #[test]
+    fn replayed_frames_are_refused() {
+        let mut controller = Authenticator::new(KEY);
+        let mut remote = Authenticator::new(KEY);
+        let mut link = SerialBuffer::<64>::new();
+        let (frame, length) = send(&mut controller, &ControllerMessage::Run, &mut link);
+        assert!(matches!(remote.try_receive(&mut link), Ok(Some(ControllerMessage::Run))));
+
+        link.extend_from_slice(&frame[..length]);
+        assert!(matches!(
+            remote.try_receive_or_reject(&mut link),
+            Err((id, RejectReason::NotAuthenticated)) if id == ControllerMessage::Run.message_id(),
+        ));
+        assert_eq!(link.count(), 0);
+
+        // Later frames are still accepted
+        send(&mut controller, &ControllerMessage::Stop, &mut link);
+        assert!(matches!(remote.try_receive(&mut link), Ok(Some(ControllerMessage::Stop))));
+    }
+
+    #[test]
+    fn frames_with_the_wrong_key_or_no_tag_are_refused() {
+        let mut controller = Authenticator::new(*b"fedcba9876543210");
+        let mut remote = Authenticator::new(KEY);
+        let mut link = SerialBuffer::<64>::new();
+        send(&mut controller, &ControllerMessage::Run, &mut link);
+        assert!(remote.try_receive(&mut link).is_err());
+
+        ControllerMessage::Run.try_send(&mut link);
+        assert!(matches!(
+            remote.try_receive_or_reject(&mut link),
+            Err((id, RejectReason::NotAuthenticated)) if id == ControllerMessage::Run.message_id(),
+        ));
+
+        ControllerMessage::EmergencyStop.try_send(&mut link);
+        assert!(matches!(remote.try_receive(&mut link), Ok(Some(ControllerMessage::EmergencyStop))));
+        assert_eq!(remote.rx_counter(), 0);
+    }
+}
@@ -0,0 +1,267 @@
+use core::future::{Future, poll_fn};
+use core::pin::pin;
+use core::task::Poll;
+use core::time::Duration;
+
+use embedded_io_async::{Read, ReadExactError, Write};
+
+use crate::{
+    ControllerMessage, Parameter, ParameterValue, Receiver, RejectReason, RemoteMessage, RunState, Statistic,
+    StatisticValue, send_async,
+};
+
+/// Time source for `QcwClient`, implemented over whichever runtime the client runs on, e.g.
+/// `embassy_time::Instant` and `Timer::after_micros`, or `tokio::time`
+#[allow(async_fn_in_trait)]
+pub trait ClientTimer {
+    /// Free running microsecond count, allowed to wrap
+    fn now_us(&mut self) -> u32;
+
+    async fn delay_us(&mut self, us: u32);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClientError<E> {
+    Io(E),
+    /// The link reached end of file
+    Disconnected,
+    /// No reply after all the retries
+    Timeout,
+    Rejected(RejectReason),
+}
+
+impl<E> From<ReadExactError<E>> for ClientError<E> {
+    fn from(error: ReadExactError<E>) -> Self {
+        match error {
+            ReadExactError::UnexpectedEof => Self::Disconnected,
+            ReadExactError::Other(error) => Self::Io(error),
+        }
+    }
+}
+
+/// Controller side request/response over an async link to the remote. Each request is sent and
+/// retried until its reply arrives or the retries run out; a `CommandRejected` for it ends the
+/// request with `ClientError::Rejected`.
+///
+/// Messages that aren't the reply being waited for, like `LockFailed`, `OcdTripped`, `Status` or
+/// `RunComplete`, are queued as events, up to `EVENTS` of them with the oldest dropped first.
+/// Take them with `try_next_event`, or wait for them with `next_event` between requests.
+pub struct QcwClient<U, T, const EVENTS: usize> {
+    uart: U,
+    timer: T,
+    receiver: Receiver<RemoteMessage>,
+    timeout_us: u32,
+    retries: u8,
+    ping_sequence: u32,
+    events: [Option<RemoteMessage>; EVENTS],
+    event_start: usize,
+    event_count: usize,
+    dropped_events: u32,
+}
+
+enum Wait<T> {
+    Done(T),
+    TimedOut,
+}
+
+impl<U: Read + Write, T: ClientTimer, const EVENTS: usize> QcwClient<U, T, EVENTS> {
+    /// Waits `timeout_us` for each reply and resends a request up to `retries` times.
+    ///
+    /// `Run` and `Stop` have no reply of their own; `run` and `stop` wait for the `Status` the
+    /// remote's `StatusBroadcaster` sends when the run state changes, so the remote must run one.
+    /// If that `Status` is lost, a resent `Run` or `Stop` changes nothing and the next `Status` only
+    /// comes after the broadcaster's `interval_ms`, so `timeout_us` should be longer than that.
+    pub fn new(uart: U, timer: T, timeout_us: u32, retries: u8) -> Self {
+        Self {
+            uart,
+            timer,
+            receiver: Receiver::new(),
+            timeout_us,
+            retries,
+            ping_sequence: 0,
+            events: [None; EVENTS],
+            event_start: 0,
+            event_count: 0,
+            dropped_events: 0,
+        }
+    }
+
+    pub fn release(self) -> (U, T) {
+        (self.uart, self.timer)
+    }
+
+    pub async fn get_param(&mut self, param: Parameter) -> Result<ParameterValue, ClientError<U::Error>> {
+        match self.request(ControllerMessage::GetParam(param), |reply| {
+            matches!(reply, RemoteMessage::GetParamResult(value) if value.parameter() == param)
+        }).await? {
+            RemoteMessage::GetParamResult(value) => Ok(value),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the value as applied by the remote, after the wire encoding's quantization
+    pub async fn set_param(&mut self, value: ParameterValue) -> Result<ParameterValue, ClientError<U::Error>> {
+        let param = value.parameter();
+        match self.request(ControllerMessage::SetParam(value), |reply| {
+            matches!(reply, RemoteMessage::ParamApplied(value) if value.parameter() == param)
+        }).await? {
+            RemoteMessage::ParamApplied(value) => Ok(value),
+            _ => unreachable!(),
+        }
+    }
+
+    pub async fn get_stat(&mut self, stat: Statistic) -> Result<StatisticValue, ClientError<U::Error>> {
+        let stat_id: u8 = stat.into();
+        match self.request(ControllerMessage::GetStat(stat), |reply| match reply {
+            RemoteMessage::GetStatResult(value) => {
+                let (stat, _): (Statistic, u16) = (*value).into();
+                Into::<u8>::into(stat) == stat_id
+            },
+            _ => false,
+        }).await? {
+            RemoteMessage::GetStatResult(value) => Ok(value),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Round trip time of a `Ping`. Each attempt uses a new sequence number, so a late reply to
+    /// an earlier attempt isn't mistaken for the current one.
+    pub async fn ping(&mut self) -> Result<Duration, ClientError<U::Error>> {
+        for _ in 0..=self.retries {
+            let sequence = self.ping_sequence;
+            self.ping_sequence = (self.ping_sequence + 1) & 0x0FFF_FFFF;
+            let sent_us = self.timer.now_us();
+            let reply = self.exchange(ControllerMessage::Ping(sequence), |reply| {
+                matches!(reply, RemoteMessage::Ping(echo) if *echo == sequence)
+            }).await?;
+            if reply.is_some() {
+                return Ok(Duration::from_micros(self.timer.now_us().wrapping_sub(sent_us) as u64));
+            }
+        }
+        Err(ClientError::Timeout)
+    }
+
+    /// Sends `Run` and waits for a `Status` showing the remote armed or firing. See `new` for
+    /// where that `Status` comes from.
+    pub async fn run(&mut self) -> Result<(), ClientError<U::Error>> {
+        self.request(ControllerMessage::Run, |reply| {
+            matches!(reply, RemoteMessage::Status(status) if matches!(status.run_state, RunState::Armed | RunState::Ramping | RunState::Running))
+        }).await.map(|_| ())
+    }
+
+    /// Sends `Stop` and waits for a `Status` showing the remote idle or faulted. See `new` for
+    /// where that `Status` comes from.
+    pub async fn stop(&mut self) -> Result<(), ClientError<U::Error>> {
+        self.request(ControllerMessage::Stop, |reply| {
+            matches!(reply, RemoteMessage::Status(status) if matches!(status.run_state, RunState::Idle | RunState::Faulted))
+        }).await.map(|_| ())
+    }
+
+    /// Sends a message that has no reply, such as `KeepAlive`
+    pub async fn send(&mut self, message: &ControllerMessage) -> Result<(), ClientError<U::Error>> {
+        send_async(&mut self.uart, message).await.map_err(ClientError::Io)
+    }
+
+    /// Takes the oldest queued event without waiting
+    pub fn try_next_event(&mut self) -> Option<RemoteMessage> {
+        if self.event_count == 0 {
+            return None;
+        }
+        let event = self.events[self.event_start].take();
+        self.event_start = (self.event_start + 1) % EVENTS;
+        self.event_count -= 1;
+        event
+    }
+
+    /// Takes the oldest queued event, or waits for the next message from the remote
+    pub async fn next_event(&mut self) -> Result<RemoteMessage, ClientError<U::Error>> {
+        if let Some(event) = self.try_next_event() {
+            return Ok(event);
+        }
+        loop {
+            if let Ok(message) = self.receiver.recv(&mut self.uart).await? {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Events lost because the queue was full
+    pub fn dropped_events(&self) -> u32 {
+        self.dropped_events
+    }
+
+    fn push_event(&mut self, event: RemoteMessage) {
+        if EVENTS == 0 {
+            self.dropped_events = self.dropped_events.wrapping_add(1);
+            return;
+        }
+        if self.event_count == EVENTS {
+            self.try_next_event();
+            self.dropped_events = self.dropped_events.wrapping_add(1);
+        }
+        self.events[(self.event_start + self.event_count) % EVENTS] = Some(event);
+        self.event_count += 1;
+    }
+
+    async fn request(
+        &mut self,
+        message: ControllerMessage,
+        matches: impl Fn(&RemoteMessage) -> bool,
+    ) -> Result<RemoteMessage, ClientError<U::Error>> {
+        for _ in 0..=self.retries {
+            if let Some(reply) = self.exchange(message, &matches).await? {
+                return Ok(reply);
+            }
+        }
+        Err(ClientError::Timeout)
+    }
+
+    // One attempt: sends `message` and waits up to the timeout for a reply matching `matches`.
+    // Returns `None` on timeout.
+    async fn exchange(
+        &mut self,
+        message: ControllerMessage,
+        matches: impl Fn(&RemoteMessage) -> bool,
+    ) -> Result<Option<RemoteMessage>, ClientError<U::Error>> {
+        send_async(&mut self.uart, &message).await.map_err(ClientError::Io)?;
+        let start_us = self.timer.now_us();
+        loop {
+            let elapsed_us = self.timer.now_us().wrapping_sub(start_us);
+            if elapsed_us >= self.timeout_us {
+                return Ok(None);
+            }
+            let received = with_timeout(
+                self.receiver.recv(&mut self.uart),
+                self.timer.delay_us(self.timeout_us - elapsed_us),
+            ).await;
+            let reply = match received {
+                Wait::Done(Ok(Ok(reply))) => reply,
+                // A garbled frame might have been the reply; keep waiting in case it's resent
+                Wait::Done(Ok(Err(_))) => continue,
+                Wait::Done(Err(error)) => return Err(error.into()),
+                Wait::TimedOut => return Ok(None),
+            };
+            match reply {
+                RemoteMessage::CommandRejected { message_id, reason } if message_id == message.message_id() => {
+                    return Err(ClientError::Rejected(reason));
+                },
+                reply if matches(&reply) => return Ok(Some(reply)),
+                // Replies to earlier pings that timed out
+                RemoteMessage::Ping(..) => {},
+                reply => self.push_event(reply),
+            }
+        }
+    }
+}
+
+// Runs `future` until `timeout` completes first. `future` must be cancel safe.
+async fn with_timeout<F: Future>(future: F, timeout: impl Future<Output = ()>) -> Wait<F::Output> {
+    let mut future = pin!(future);
+    let mut timeout = pin!(timeout);
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Wait::Done(output));
+        }
+        timeout.as_mut().poll(cx).map(|()| Wait::TimedOut)
+    }).await
+}
@@ -0,0 +1,223 @@
+use crate::{RejectReason, RemoteMessage};
+
+// All times are free running microsecond counters that wrap at u32::MAX, so only differences
+// between them are meaningful.
+
+/// One offset measurement from a `ClockSyncRequest`/`ClockSyncResponse` exchange
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ClockSample {
+    /// Remote clock minus controller clock
+    pub offset_us: i32,
+    /// Time spent on the link, excluding the remote's turnaround
+    pub round_trip_us: u32,
+}
+
+impl ClockSample {
+    pub fn new(controller_send_time: u32, remote_receive_time: u32, remote_send_time: u32, controller_receive_time: u32) -> Self {
+        let outbound = remote_receive_time.wrapping_sub(controller_send_time) as i32 as i64;
+        let inbound = remote_send_time.wrapping_sub(controller_receive_time) as i32 as i64;
+        let round_trip_us = controller_receive_time.wrapping_sub(controller_send_time)
+            .saturating_sub(remote_send_time.wrapping_sub(remote_receive_time));
+        Self {
+            offset_us: ((outbound + inbound) / 2) as i32,
+            round_trip_us,
+        }
+    }
+}
+
+/// Controller side estimate of one remote's clock, used to translate controller times into the
+/// remote's clock for `ControllerMessage::RunAt`. Samples with a round trip longer than
+/// `max_round_trip_us` are ignored, since the link delay makes their offset unreliable.
+pub struct ClockDiscipline {
+    max_round_trip_us: u32,
+    samples: u32,
+    reference_time: u32,
+    offset_us: i32,
+    drift: f32,
+}
+
+impl ClockDiscipline {
+    const DRIFT_GAIN: f32 = 0.25;
+
+    pub fn new(max_round_trip_us: u32) -> Self {
+        Self {
+            max_round_trip_us,
+            samples: 0,
+            reference_time: 0,
+            offset_us: 0,
+            drift: 0.0,
+        }
+    }
+
+    /// Feeds a received message, returning true if it was a usable `ClockSyncResponse`
+    pub fn update(&mut self, message: &RemoteMessage, controller_receive_time: u32) -> bool {
+        if let RemoteMessage::ClockSyncResponse { controller_time, receive_time, send_time } = message {
+            let sample = ClockSample::new(*controller_time, *receive_time, *send_time, controller_receive_time);
+            self.add_sample(*controller_time, sample)
+        } else {
+            false
+        }
+    }
+
+    pub fn add_sample(&mut self, controller_time: u32, sample: ClockSample) -> bool {
+        if sample.round_trip_us > self.max_round_trip_us {
+            return false;
+        }
+        if self.samples > 0 {
+            let elapsed_us = controller_time.wrapping_sub(self.reference_time) as i32;
+            if elapsed_us <= 0 {
+                return false;
+            }
+            let drift = sample.offset_us.wrapping_sub(self.offset_us) as f32 / elapsed_us as f32;
+            self.drift = if self.samples == 1 {
+                drift
+            } else {
+                self.drift + (drift - self.drift) * Self::DRIFT_GAIN
+            };
+        }
+        self.samples = self.samples.saturating_add(1);
+        self.reference_time = controller_time;
+        self.offset_us = sample.offset_us;
+        true
+    }
+
+    pub fn is_synchronized(&self) -> bool {
+        self.samples > 0
+    }
+
+    pub fn offset_us(&self) -> i32 {
+        self.offset_us
+    }
+
+    /// Remote clock rate relative to the controller's, in parts per million
+    pub fn drift_ppm(&self) -> f32 {
+        self.drift * 1_000_000.0
+    }
+
+    pub fn to_remote_time(&self, controller_time: u32) -> u32 {
+        // The offset stays an integer; only the drift correction goes through f32, which can't
+        // hold a large offset to the microsecond
+        let elapsed_us = controller_time.wrapping_sub(self.reference_time) as i32;
+        controller_time
+            .wrapping_add(self.offset_us as u32)
+            .wrapping_add((self.drift * elapsed_us as f32) as i32 as u32)
+    }
+}
+
+/// Remote side: holds a `RunAt` time and says when the local clock reaches it
+pub struct ScheduledRun {
+    at: Option<u32>,
+}
+
+impl ScheduledRun {
+    pub fn new() -> Self {
+        Self { at: None }
+    }
+
+    /// Refuses times that have already passed, since starting late would defeat synchronization
+    pub fn schedule(&mut self, at: u32, now: u32) -> Result<(), RejectReason> {
+        if (now.wrapping_sub(at) as i32) >= 0 {
+            return Err(RejectReason::TooLate);
+        }
+        self.at = Some(at);
+        Ok(())
+    }
+
+    pub fn cancel(&mut self) {
+        self.at = None;
+    }
+
+    pub fn scheduled(&self) -> Option<u32> {
+        self.at
+    }
+
+    /// Returns true exactly once, on the first poll at or after the scheduled time
+    pub fn poll(&mut self, now: u32) -> bool {
+        match self.at {
+            Some(at) if (now.wrapping_sub(at) as i32) >= 0 => {
+                self.at = None;
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+impl Default for ScheduledRun {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A remote `offset_us` ahead of the controller, `delay_us` away each way, taking `turnaround_us`
+    // to answer a request sent at `controller_time`
+    fn exchange(controller_time: u32, offset_us: i32, delay_us: u32, turnaround_us: u32) -> RemoteMessage {
+        let receive_time = controller_time.wrapping_add(delay_us).wrapping_add(offset_us as u32);
+        RemoteMessage::ClockSyncResponse {
+            controller_time,
+            receive_time,
+            send_time: receive_time.wrapping_add(turnaround_us),
+        }
+    }
+
+    fn reply_time(controller_time: u32, delay_us: u32, turnaround_us: u32) -> u32 {
+        controller_time.wrapping_add(2 * delay_us + turnaround_us)
+    }
+
+    #[test]
+    fn sample_measures_offset_and_link_time_across_wrap_around() {
+        for (controller_time, offset_us) in [(1000, 5000), (u32::MAX - 50, 5000), (1000, -2_000_000_000)] {
+            let RemoteMessage::ClockSyncResponse { receive_time, send_time, .. } = exchange(controller_time, offset_us, 100, 50) else {
+                unreachable!()
+            };
+            let sample = ClockSample::new(controller_time, receive_time, send_time, reply_time(controller_time, 100, 50));
+            assert_eq!(sample, ClockSample { offset_us, round_trip_us: 200 });
+        }
+    }
+
+    #[test]
+    fn slow_samples_and_samples_out_of_order_are_ignored() {
+        let mut clock = ClockDiscipline::new(500);
+        assert!(!clock.update(&exchange(0, 5000, 300, 0), reply_time(0, 300, 0)));
+        assert!(!clock.update(&RemoteMessage::EmergencyStopAck, 0));
+        assert!(!clock.is_synchronized());
+
+        assert!(clock.update(&exchange(1000, 5000, 100, 0), reply_time(1000, 100, 0)));
+        assert!(!clock.update(&exchange(1000, 6000, 100, 0), reply_time(1000, 100, 0)));
+        assert_eq!(clock.offset_us(), 5000);
+    }
+
+    #[test]
+    fn remote_time_follows_drift() {
+        let mut clock = ClockDiscipline::new(500);
+        assert!(clock.update(&exchange(0, 5000, 100, 0), reply_time(0, 100, 0)));
+        assert_eq!(clock.to_remote_time(1_000_000), 1_005_000);
+        // The remote runs 100 ppm fast
+        assert!(clock.update(&exchange(1_000_000, 5100, 100, 0), reply_time(1_000_000, 100, 0)));
+        assert!((clock.drift_ppm() - 100.0).abs() < 0.01);
+        assert_eq!(clock.to_remote_time(2_000_000), 2_005_200);
+    }
+
+    #[test]
+    fn large_offsets_translate_to_the_microsecond() {
+        let mut clock = ClockDiscipline::new(500);
+        assert!(clock.update(&exchange(0, 1_000_000_007, 100, 0), reply_time(0, 100, 0)));
+        assert_eq!(clock.to_remote_time(12_345), 1_000_012_352);
+        assert_eq!(clock.to_remote_time(u32::MAX), 1_000_000_006);
+    }
+
+    #[test]
+    fn scheduled_run_fires_once_at_its_time() {
+        let mut run = ScheduledRun::new();
+        assert_eq!(run.schedule(1000, 1000), Err(RejectReason::TooLate));
+        assert_eq!(run.schedule(5, u32::MAX - 5), Ok(()));
+        assert!(!run.poll(u32::MAX));
+        assert!(run.poll(6));
+        assert!(!run.poll(7));
+        assert_eq!(run.scheduled(), None);
+    }
+}
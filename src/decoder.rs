@@ -0,0 +1,276 @@
+use core::marker::PhantomData;
+
+use crate::{
+    CONTROLLER_MESSAGE_ID_EMERGENCY_STOP, ControllerMessage, DecodeError, MAX_FRAME_LENGTH, MESSAGE_START_BIT, RemoteMessage,
+    UNMARKED_EMERGENCY_STOP, is_emergency_stop_id,
+};
+
+/// A message type that can be framed, for code generic over `ControllerMessage` and
+/// `RemoteMessage`
+pub trait Message: Sized {
+    /// A frame that is still recognised after losing its start bit, as it arrives without it
+    const UNMARKED_FRAME: &'static [u8] = &[];
+
+    /// Length of the frame starting with `message_id`, start byte included, or `None` for an
+    /// unknown id. Includes any ids `decode` tolerates as corrupted versions of a known one.
+    fn frame_len(message_id: u8) -> Option<usize>;
+
+    /// Frames with this id are read to their full length even when a byte with the start bit
+    /// set turns up part way, which `decode` then takes as a corrupted payload byte
+    fn ignores_start_bit_in_payload(_message_id: u8) -> bool {
+        false
+    }
+
+    #[allow(clippy::result_unit_err)]
+    fn encode(&self, out: &mut [u8]) -> Result<usize, ()>;
+
+    fn decode(input: &[u8]) -> Result<(Self, usize), DecodeError>;
+}
+
+impl Message for ControllerMessage {
+    const UNMARKED_FRAME: &'static [u8] = &UNMARKED_EMERGENCY_STOP;
+
+    fn frame_len(message_id: u8) -> Option<usize> {
+        ControllerMessage::encoded_len_of(if is_emergency_stop_id(message_id) { CONTROLLER_MESSAGE_ID_EMERGENCY_STOP } else { message_id })
+    }
+
+    fn ignores_start_bit_in_payload(message_id: u8) -> bool {
+        is_emergency_stop_id(message_id)
+    }
+
+    fn encode(&self, out: &mut [u8]) -> Result<usize, ()> {
+        ControllerMessage::encode(self, out)
+    }
+
+    fn decode(input: &[u8]) -> Result<(Self, usize), DecodeError> {
+        ControllerMessage::decode(input)
+    }
+}
+
+impl Message for RemoteMessage {
+    fn frame_len(message_id: u8) -> Option<usize> {
+        RemoteMessage::encoded_len_of(message_id)
+    }
+
+    fn encode(&self, out: &mut [u8]) -> Result<usize, ()> {
+        RemoteMessage::encode(self, out)
+    }
+
+    fn decode(input: &[u8]) -> Result<(Self, usize), DecodeError> {
+        RemoteMessage::decode(input)
+    }
+}
+
+/// Incremental frame decoder, fed one byte at a time as they arrive, e.g. from the UART receive
+/// interrupt. Each byte takes constant time, apart from the byte completing a frame, which
+/// decodes it.
+///
+/// Bytes outside a frame are skipped. A start byte in the middle of a frame means the rest of
+/// that frame was lost, so the partial frame is reported as `DecodeError::Invalid` and decoding
+/// starts over from the new start byte. If that start byte is a whole frame by itself, its
+/// message is returned instead and the lost frame goes unreported.
+///
+/// An `EmergencyStop` is decoded even with any single bit corrupted, see `ControllerMessage`.
+pub struct Decoder<M: Message> {
+    frame: [u8; MAX_FRAME_LENGTH],
+    received: usize,
+    length: usize,
+    // How much of `M::UNMARKED_FRAME` the latest skipped bytes match
+    unmarked: usize,
+    _message: PhantomData<M>,
+}
+
+impl<M: Message> Decoder<M> {
+    pub const fn new() -> Self {
+        Self {
+            frame: [0; MAX_FRAME_LENGTH],
+            received: 0,
+            length: 0,
+            unmarked: 0,
+            _message: PhantomData,
+        }
+    }
+
+    /// A frame has been started but not yet completed
+    pub fn is_in_frame(&self) -> bool {
+        self.received > 0
+    }
+
+    /// Drops any partial frame
+    pub fn reset(&mut self) {
+        self.received = 0;
+        self.unmarked = 0;
+    }
+
+    /// Takes the next received byte. Returns the message or error once a frame is complete,
+    /// `None` otherwise. `DecodeError::Invalid` gives the number of bytes of the frame dropped.
+    pub fn feed(&mut self, byte: u8) -> Option<Result<M, DecodeError>> {
+        let in_payload = self.received > 0 && M::ignores_start_bit_in_payload(self.frame[0] & !MESSAGE_START_BIT);
+        if (byte & MESSAGE_START_BIT) == 0 || in_payload {
+            if self.received == 0 {
+                return self.skip(byte);
+            }
+            self.frame[self.received] = byte;
+            self.received += 1;
+            return self.finish();
+        }
+        self.unmarked = 0;
+        let interrupted = self.received;
+        self.received = 0;
+        let Some(length) = M::frame_len(byte & !MESSAGE_START_BIT) else {
+            return Some(Err(DecodeError::Invalid { consumed: interrupted + 1 }));
+        };
+        self.frame[0] = byte;
+        self.received = 1;
+        self.length = length.min(MAX_FRAME_LENGTH);
+        match self.finish() {
+            None if interrupted > 0 => Some(Err(DecodeError::Invalid { consumed: interrupted })),
+            result => result,
+        }
+    }
+
+    // Outside a frame: watches for `M::UNMARKED_FRAME` among the skipped bytes
+    fn skip(&mut self, byte: u8) -> Option<Result<M, DecodeError>> {
+        let pattern = M::UNMARKED_FRAME;
+        if pattern.is_empty() {
+            return None;
+        }
+        // Longest end of the matched bytes plus this one that is a start of the pattern
+        let matched = self.unmarked;
+        self.unmarked = (1..=(matched + 1).min(pattern.len()))
+            .rev()
+            .find(|&length| {
+                let start = matched + 1 - length;
+                pattern[start..matched] == pattern[..length - 1] && pattern[length - 1] == byte
+            })
+            .unwrap_or(0);
+        if self.unmarked < pattern.len() {
+            return None;
+        }
+        self.unmarked = 0;
+        self.frame[..pattern.len()].copy_from_slice(pattern);
+        self.frame[0] |= MESSAGE_START_BIT;
+        self.received = pattern.len();
+        self.length = pattern.len();
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Option<Result<M, DecodeError>> {
+        if self.received < self.length {
+            return None;
+        }
+        let length = self.received;
+        self.received = 0;
+        Some(match M::decode(&self.frame[..length]) {
+            Ok((message, _)) => Ok(message),
+            Err(_) => Err(DecodeError::Invalid { consumed: length }),
+        })
+    }
+}
+
+impl<M: Message> Default for Decoder<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Parameter;
+
+    // The completed frames, in order
+    fn feed_all(decoder: &mut Decoder<ControllerMessage>, bytes: &[u8]) -> [Option<Result<ControllerMessage, DecodeError>>; 4] {
+        let mut results = [None, None, None, None];
+        let mut completed = bytes.iter().filter_map(|byte| decoder.feed(*byte));
+        for slot in &mut results {
+            *slot = completed.next();
+        }
+        assert!(completed.next().is_none());
+        results
+    }
+
+    fn ping() -> ([u8; MAX_FRAME_LENGTH], usize) {
+        let mut frame = [0u8; MAX_FRAME_LENGTH];
+        let length = ControllerMessage::Ping(0x0123_4567).encode(&mut frame).unwrap();
+        (frame, length)
+    }
+
+    #[test]
+    fn frames_split_by_line_noise_are_decoded() {
+        let (frame, length) = ping();
+        let mut decoder = Decoder::<ControllerMessage>::new();
+        assert!(matches!(feed_all(&mut decoder, &[0x12, 0x34]), [None, None, None, None]));
+        assert!(matches!(feed_all(&mut decoder, &frame[..2]), [None, None, None, None]));
+        assert!(decoder.is_in_frame());
+        assert!(matches!(feed_all(&mut decoder, &frame[2..length]), [Some(Ok(ControllerMessage::Ping(0x0123_4567))), None, None, None]));
+        assert!(!decoder.is_in_frame());
+    }
+
+    #[test]
+    fn an_interrupted_frame_is_dropped_and_decoding_resyncs() {
+        let (frame, length) = ping();
+        let mut decoder = Decoder::<ControllerMessage>::new();
+        assert!(matches!(feed_all(&mut decoder, &frame[..3]), [None, None, None, None]));
+        assert!(matches!(
+            feed_all(&mut decoder, &frame[..length]),
+            [Some(Err(DecodeError::Invalid { consumed: 3 })), Some(Ok(ControllerMessage::Ping(0x0123_4567))), None, None]
+        ));
+
+        // A single byte frame interrupting another takes its place
+        assert!(matches!(feed_all(&mut decoder, &frame[..3]), [None, None, None, None]));
+        let mut run = [0u8; 1];
+        ControllerMessage::Run.encode(&mut run).unwrap();
+        assert!(matches!(feed_all(&mut decoder, &run), [Some(Ok(ControllerMessage::Run)), None, None, None]));
+        assert!(!decoder.is_in_frame());
+    }
+
+    #[test]
+    fn unknown_ids_and_undecodable_frames_are_invalid() {
+        let mut decoder = Decoder::<ControllerMessage>::new();
+        assert!(matches!(feed_all(&mut decoder, &[0x70 | MESSAGE_START_BIT]), [Some(Err(DecodeError::Invalid { consumed: 1 })), None, None, None]));
+
+        // Interrupting two bytes of a ping counts them too
+        let (frame, _) = ping();
+        assert!(matches!(
+            feed_all(&mut decoder, &[frame[0], frame[1], 0x70 | MESSAGE_START_BIT]),
+            [Some(Err(DecodeError::Invalid { consumed: 3 })), None, None, None]
+        ));
+
+        // A `GetParam` of a parameter that doesn't exist
+        let mut get_param = [0u8; 2];
+        ControllerMessage::GetParam(Parameter::OnTime).encode(&mut get_param).unwrap();
+        get_param[1] = 0x7F;
+        assert!(matches!(feed_all(&mut decoder, &get_param), [Some(Err(DecodeError::Invalid { consumed: 2 })), None, None, None]));
+    }
+
+    #[test]
+    fn an_emergency_stop_without_its_start_bit_is_recovered() {
+        let mut decoder = Decoder::<ControllerMessage>::new();
+        let mut bytes = [0u8; 7];
+        bytes[0] = 0x55;
+        bytes[1] = 0x01;
+        bytes[2..].copy_from_slice(&UNMARKED_EMERGENCY_STOP);
+        assert!(matches!(feed_all(&mut decoder, &bytes), [Some(Ok(ControllerMessage::EmergencyStop)), None, None, None]));
+
+        // Remote messages don't have one
+        let mut decoder = Decoder::<RemoteMessage>::new();
+        assert!(UNMARKED_EMERGENCY_STOP.iter().all(|byte| decoder.feed(*byte).is_none()));
+    }
+
+    #[test]
+    fn reset_drops_a_partial_frame() {
+        let (frame, length) = ping();
+        let mut decoder = Decoder::<ControllerMessage>::new();
+        assert!(matches!(feed_all(&mut decoder, &frame[..3]), [None, None, None, None]));
+        decoder.reset();
+        assert!(!decoder.is_in_frame());
+        assert!(matches!(feed_all(&mut decoder, &frame[3..length]), [None, None, None, None]));
+        assert!(matches!(feed_all(&mut decoder, &frame[..length]), [Some(Ok(ControllerMessage::Ping(0x0123_4567))), None, None, None]));
+
+        // Including one matching an unmarked emergency stop
+        assert!(matches!(feed_all(&mut decoder, &UNMARKED_EMERGENCY_STOP[..4]), [None, None, None, None]));
+        decoder.reset();
+        assert!(matches!(feed_all(&mut decoder, &UNMARKED_EMERGENCY_STOP[4..]), [None, None, None, None]));
+    }
+}
@@ -0,0 +1,748 @@
+use crate::{
+    AddressFilter, ByteSink, ByteSource, ControllerMessage, LimitProfile, Parameter, ParameterLimit, ParameterSet,
+    ParameterStore, ParameterValue, RampPoint, RejectReason, RemoteMessage, Statistic, StatisticValue, Transaction,
+};
+#[cfg(feature = "auth")]
+use crate::Authenticator;
+
+/// Remote side receive path. Decodes the next message from `rx_buffer` and checks it with
+/// `validate`; if either fails, a `RemoteMessage::CommandRejected` is queued in `tx_buffer` and the
+/// reason is returned. `EmergencyStop` is never passed to `validate`, can't be rejected and is
+/// always answered with `RemoteMessage::EmergencyStopAck`.
+///
+/// An accepted `SetParam` is answered with `RemoteMessage::ParamApplied` carrying the value as
+/// decoded, which already reflects the quantization and clamping of the wire encoding. The
+/// firmware is expected to apply exactly that value.
+///
+/// `transaction_open` is `Transaction::is_open`. While it's true an accepted `SetParam` is only
+/// staged, so it isn't answered; the `Commit` reply confirms the staged values instead.
+pub fn dispatch(
+    rx_buffer: &mut impl ByteSource,
+    tx_buffer: &mut impl ByteSink,
+    transaction_open: bool,
+    validate: impl FnOnce(&ControllerMessage) -> Result<(), RejectReason>,
+) -> Result<Option<ControllerMessage>, RejectReason> {
+    respond(ControllerMessage::try_receive_or_reject(rx_buffer), |message| validated(message, transaction_open, validate), |reply| {
+        reply.try_send(tx_buffer);
+    })
+}
+
+/// Same as `dispatch`, but only accepts frames authenticated by `authenticator`
+#[cfg(feature = "auth")]
+pub fn dispatch_authenticated(
+    authenticator: &mut Authenticator,
+    rx_buffer: &mut impl ByteSource,
+    tx_buffer: &mut impl ByteSink,
+    transaction_open: bool,
+    validate: impl FnOnce(&ControllerMessage) -> Result<(), RejectReason>,
+) -> Result<Option<ControllerMessage>, RejectReason> {
+    respond(authenticator.try_receive_or_reject(rx_buffer), |message| validated(message, transaction_open, validate), |reply| {
+        reply.try_send(tx_buffer);
+    })
+}
+
+/// Same as `dispatch`, but only accepts frames addressed to `filter`'s remote or broadcast. Replies
+/// carry this remote's address, and broadcasts are never replied to.
+pub fn dispatch_addressed(
+    filter: &AddressFilter,
+    rx_buffer: &mut impl ByteSource,
+    tx_buffer: &mut impl ByteSink,
+    transaction_open: bool,
+    validate: impl FnOnce(&ControllerMessage) -> Result<(), RejectReason>,
+) -> Result<Option<ControllerMessage>, RejectReason> {
+    let (received, broadcast) = receive_addressed(filter, rx_buffer);
+    respond(received, |message| validated(message, transaction_open, validate), |reply| {
+        if !broadcast {
+            filter.try_send(reply, tx_buffer);
+        }
+    })
+}
+
+// Splits off whether the message was broadcast, so neither it nor its rejection is answered
+fn receive_addressed(
+    filter: &AddressFilter,
+    rx_buffer: &mut impl ByteSource,
+) -> (Result<Option<ControllerMessage>, (u8, RejectReason)>, bool) {
+    match filter.try_receive_or_reject(rx_buffer) {
+        Ok(Some((message, broadcast))) => (Ok(Some(message)), broadcast),
+        Ok(None) => (Ok(None), false),
+        Err((message_id, reason, broadcast)) => (Err((message_id, reason)), broadcast),
+    }
+}
+
+// The answer the free `dispatch` functions give an accepted message
+fn validated(
+    message: &ControllerMessage,
+    transaction_open: bool,
+    validate: impl FnOnce(&ControllerMessage) -> Result<(), RejectReason>,
+) -> Result<Option<RemoteMessage>, RejectReason> {
+    match *message {
+        ControllerMessage::EmergencyStop => Ok(Some(RemoteMessage::EmergencyStopAck)),
+        ControllerMessage::SetParam(value) => {
+            validate(message)?;
+            Ok((!transaction_open).then_some(RemoteMessage::ParamApplied(value)))
+        },
+        _ => validate(message).map(|_| None),
+    }
+}
+
+// Shared by every receive path: passes a received message to `handle` and sends its answer, or
+// the `CommandRejected` if it couldn't be decoded or `handle` refused it
+fn respond(
+    received: Result<Option<ControllerMessage>, (u8, RejectReason)>,
+    handle: impl FnOnce(&ControllerMessage) -> Result<Option<RemoteMessage>, RejectReason>,
+    mut reply: impl FnMut(&RemoteMessage),
+) -> Result<Option<ControllerMessage>, RejectReason> {
+    let result = match received {
+        Ok(Some(message)) => handle(&message)
+            .map(|answer| (message, answer))
+            .map_err(|reason| (message.message_id(), reason)),
+        Ok(None) => return Ok(None),
+        Err(rejection) => Err(rejection),
+    };
+    match result {
+        Ok((message, answer)) => {
+            if let Some(answer) = answer {
+                reply(&answer);
+            }
+            Ok(Some(message))
+        },
+        Err((message_id, reason)) => {
+            reply(&RemoteMessage::CommandRejected { message_id, reason });
+            Err(reason)
+        },
+    }
+}
+
+/// Remote side handling of each command, for use with `Dispatcher`. A command is accepted by
+/// returning `Ok` and refused with the `RejectReason` to send back. Commands a firmware doesn't
+/// implement are refused with `RejectReason::UnknownMessage` by default.
+pub trait RemoteHandler {
+    /// Called before any other method for every command except `EmergencyStop`, to refuse
+    /// commands as a whole, e.g. `Run` while faulted. Rules across parameters belong in
+    /// `validate_parameters`, since this also sees every `SetParam` staged in a transaction.
+    fn validate(&mut self, _message: &ControllerMessage) -> Result<(), RejectReason> {
+        Ok(())
+    }
+
+    /// Checks a full parameter set before the default `on_set_param` or `on_commit` puts it into
+    /// `parameter_store`, e.g. with `Interlocks::check`. Not called for values staged in a
+    /// transaction, only for the whole set on commit.
+    fn validate_parameters(&mut self, _set: &ParameterSet) -> Result<(), RejectReason> {
+        Ok(())
+    }
+
+    /// Can't be refused, and is always answered with `EmergencyStopAck`
+    fn on_emergency_stop(&mut self);
+
+    /// The store `on_get_param`, `on_set_param` and `on_commit` use unless they're overridden
+    fn parameter_store(&mut self) -> Option<&mut ParameterStore> {
+        None
+    }
+
+    /// The installation caps the default `on_set_param` and `on_commit` enforce, and the default
+    /// limit and engineering unlock methods use
+    fn limit_profile(&mut self) -> Option<&mut LimitProfile> {
+        None
+    }
+
+    /// Millisecond clock for the default `on_engineering_unlock`'s lockout after wrong PINs.
+    /// Override it along with `limit_profile`; left at 0 a lockout never ends.
+    fn now_ms(&mut self) -> u32 {
+        0
+    }
+
+    /// Where `on_set_param` stages values while a transaction is open, unless it's overridden.
+    /// Used by the default transaction methods, which also need `parameter_store`.
+    fn transaction(&mut self) -> Option<&mut Transaction> {
+        None
+    }
+
+    /// Answered with `GetParamResult`
+    fn on_get_param(&mut self, param: Parameter) -> Result<ParameterValue, RejectReason> {
+        match self.parameter_store() {
+            Some(store) => Ok(store.get(param)),
+            None => Err(RejectReason::UnknownMessage),
+        }
+    }
+
+    /// Returns the value in effect afterwards, e.g. once clamped, answered with `ParamApplied`.
+    /// While `transaction` has one open the value is only staged there and isn't answered.
+    fn on_set_param(&mut self, value: ParameterValue) -> Result<ParameterValue, RejectReason> {
+        if let Some(limits) = self.limit_profile() {
+            limits.check(value)?;
+        }
+        if let Some(transaction) = self.transaction() && transaction.is_open() {
+            ParameterStore::validate(value)?;
+            transaction.stage(value)?;
+            return Ok(value);
+        }
+        let Some(current) = self.parameter_store().map(|store| *store.parameters()) else {
+            return Err(RejectReason::UnknownMessage);
+        };
+        ParameterStore::validate(value)?;
+        self.validate_parameters(&current.with(value))?;
+        match self.parameter_store() {
+            Some(store) => {
+                store.set(value)?;
+                Ok(store.get(value.parameter()))
+            },
+            None => Err(RejectReason::UnknownMessage),
+        }
+    }
+
+    fn on_run(&mut self) -> Result<(), RejectReason>;
+
+    fn on_stop(&mut self) -> Result<(), RejectReason>;
+
+    /// Answered with the same `Ping`
+    fn on_ping(&mut self, _sequence: u32) {}
+
+    fn on_keep_alive(&mut self) -> Result<(), RejectReason> {
+        Ok(())
+    }
+
+    fn on_set_debug_led(&mut self, _state: bool) -> Result<(), RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+
+    /// Answered with `GetStatResult`
+    fn on_get_stat(&mut self, _stat: Statistic) -> Result<StatisticValue, RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+
+    fn on_reset_stats(&mut self) -> Result<(), RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+
+    fn on_begin_transaction(&mut self) -> Result<(), RejectReason> {
+        match self.transaction() {
+            Some(transaction) => {
+                transaction.begin();
+                Ok(())
+            },
+            None => Err(RejectReason::UnknownMessage),
+        }
+    }
+
+    /// Answered with `TransactionCommitted`. By default the staged values go into
+    /// `parameter_store` all at once, or none of them if any is out of range or over a cap, or
+    /// the resulting set fails `validate_parameters`.
+    fn on_commit(&mut self) -> Result<(), RejectReason> {
+        let Some(current) = self.parameter_store().map(|store| *store.parameters()) else {
+            return Err(RejectReason::UnknownMessage);
+        };
+        let Some(transaction) = self.transaction() else {
+            return Err(RejectReason::UnknownMessage);
+        };
+        let set = transaction.commit(&current, |set| set.values().try_for_each(ParameterStore::validate))?;
+        // The caps may have changed since the values were staged
+        if let Some(limits) = self.limit_profile() {
+            limits.check_set(&set)?;
+        }
+        self.validate_parameters(&set)?;
+        match self.parameter_store() {
+            Some(store) => store.set_all(&set),
+            None => Err(RejectReason::UnknownMessage),
+        }
+    }
+
+    fn on_abort(&mut self) -> Result<(), RejectReason> {
+        match self.transaction() {
+            Some(transaction) => transaction.abort(),
+            None => Err(RejectReason::UnknownMessage),
+        }
+    }
+
+    /// Answered with `LimitResult`
+    fn on_get_limit(&mut self, param: Parameter) -> Result<ParameterLimit, RejectReason> {
+        match self.limit_profile() {
+            Some(limits) => Ok(limits.limit(param)),
+            None => Err(RejectReason::UnknownMessage),
+        }
+    }
+
+    /// Answered with `LimitResult`. By default a value in `parameter_store` outside the new limit
+    /// is brought within it straight away.
+    fn on_set_limit(&mut self, limit: ParameterLimit) -> Result<(), RejectReason> {
+        match self.limit_profile() {
+            Some(limits) => limits.set_limit(limit)?,
+            None => return Err(RejectReason::UnknownMessage),
+        }
+        if let Some(store) = self.parameter_store() {
+            let current = store.get(limit.parameter());
+            store.set(limit.clamp(current))?;
+        }
+        Ok(())
+    }
+
+    /// Answered with `EngineeringUnlocked`
+    fn on_engineering_unlock(&mut self, pin: u32) -> Result<(), RejectReason> {
+        let now_ms = self.now_ms();
+        match self.limit_profile() {
+            Some(limits) => limits.unlock(pin, now_ms),
+            None => Err(RejectReason::UnknownMessage),
+        }
+    }
+
+    /// Answered with `EngineeringLocked`
+    fn on_engineering_lock(&mut self) -> Result<(), RejectReason> {
+        match self.limit_profile() {
+            Some(limits) => {
+                limits.lock();
+                Ok(())
+            },
+            None => Err(RejectReason::UnknownMessage),
+        }
+    }
+
+    /// Returns the remote's clock, answered with a `ClockSyncResponse` using it as both the
+    /// receive and send time
+    fn on_clock_sync_request(&mut self) -> Result<u32, RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+
+    fn on_run_at(&mut self, _timestamp: u32) -> Result<(), RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+
+    fn on_fire_single(&mut self) -> Result<(), RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+
+    fn on_fire_burst(&mut self, _count: u16) -> Result<(), RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+
+    fn on_run_for(&mut self, _duration_ms: u32) -> Result<(), RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+
+    fn on_note_on(&mut self, _note: u8, _velocity: u8) -> Result<(), RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+
+    fn on_note_off(&mut self, _note: u8) -> Result<(), RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+
+    fn on_all_notes_off(&mut self) -> Result<(), RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+
+    fn on_begin_ramp_upload(&mut self, _point_count: u8) -> Result<(), RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+
+    fn on_upload_ramp_point(&mut self, _index: u8, _point: RampPoint) -> Result<(), RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+
+    /// Returns the number of points in the new profile, answered with `RampProfileCommitted`
+    fn on_commit_ramp_upload(&mut self) -> Result<u8, RejectReason> {
+        Err(RejectReason::UnknownMessage)
+    }
+}
+
+/// Remote side receive path built on a `RemoteHandler`: decodes each message, calls the matching
+/// handler method and queues the reply or the `CommandRejected`.
+///
+/// Keeps track of whether the handler has a transaction open, from an accepted `BeginTransaction`
+/// until the next `Commit` or `Abort`, and doesn't answer a `SetParam` with `ParamApplied` while
+/// one is, since the value is only staged.
+pub struct Dispatcher<H: RemoteHandler> {
+    handler: H,
+    transaction_open: bool,
+}
+
+impl<H: RemoteHandler> Dispatcher<H> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            transaction_open: false,
+        }
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    pub fn into_handler(self) -> H {
+        self.handler
+    }
+
+    pub fn is_transaction_open(&self) -> bool {
+        self.transaction_open
+    }
+
+    /// Handles the next message in `rx_buffer`, if one is complete, and returns it
+    pub fn poll(
+        &mut self,
+        rx_buffer: &mut impl ByteSource,
+        tx_buffer: &mut impl ByteSink,
+    ) -> Result<Option<ControllerMessage>, RejectReason> {
+        respond(ControllerMessage::try_receive_or_reject(rx_buffer), |message| self.handle(message), |reply| {
+            reply.try_send(tx_buffer);
+        })
+    }
+
+    /// Same as `poll`, but only accepts frames authenticated by `authenticator`
+    #[cfg(feature = "auth")]
+    pub fn poll_authenticated(
+        &mut self,
+        authenticator: &mut Authenticator,
+        rx_buffer: &mut impl ByteSource,
+        tx_buffer: &mut impl ByteSink,
+    ) -> Result<Option<ControllerMessage>, RejectReason> {
+        respond(authenticator.try_receive_or_reject(rx_buffer), |message| self.handle(message), |reply| {
+            reply.try_send(tx_buffer);
+        })
+    }
+
+    /// Same as `poll`, but only accepts frames addressed to `filter`'s remote or broadcast. Replies
+    /// carry this remote's address, and broadcasts are never replied to.
+    pub fn poll_addressed(
+        &mut self,
+        filter: &AddressFilter,
+        rx_buffer: &mut impl ByteSource,
+        tx_buffer: &mut impl ByteSink,
+    ) -> Result<Option<ControllerMessage>, RejectReason> {
+        let (received, broadcast) = receive_addressed(filter, rx_buffer);
+        respond(received, |message| self.handle(message), |reply| {
+            if !broadcast {
+                filter.try_send(reply, tx_buffer);
+            }
+        })
+    }
+
+    /// Passes an already decoded message to the handler and returns the reply to send, for
+    /// receive paths other than the `poll` methods'
+    pub fn handle(&mut self, message: &ControllerMessage) -> Result<Option<RemoteMessage>, RejectReason> {
+        let handler = &mut self.handler;
+        if let ControllerMessage::EmergencyStop = message {
+            handler.on_emergency_stop();
+            return Ok(Some(RemoteMessage::EmergencyStopAck));
+        }
+        handler.validate(message)?;
+        Ok(match *message {
+            ControllerMessage::SetDebugLed(state) => { handler.on_set_debug_led(state)?; None },
+            ControllerMessage::GetParam(param) => Some(RemoteMessage::GetParamResult(handler.on_get_param(param)?)),
+            ControllerMessage::SetParam(value) => {
+                let value = handler.on_set_param(value)?;
+                (!self.transaction_open).then_some(RemoteMessage::ParamApplied(value))
+            },
+            ControllerMessage::GetStat(stat) => Some(RemoteMessage::GetStatResult(handler.on_get_stat(stat)?)),
+            ControllerMessage::ResetStats => { handler.on_reset_stats()?; None },
+            ControllerMessage::KeepAlive => { handler.on_keep_alive()?; None },
+            ControllerMessage::Run => { handler.on_run()?; None },
+            ControllerMessage::Stop => { handler.on_stop()?; None },
+            ControllerMessage::EmergencyStop => None,
+            ControllerMessage::BeginTransaction => {
+                handler.on_begin_transaction()?;
+                self.transaction_open = true;
+                None
+            },
+            // Closed whether or not the handler accepts them, as `Transaction::commit` does
+            ControllerMessage::Commit => {
+                self.transaction_open = false;
+                handler.on_commit()?;
+                Some(RemoteMessage::TransactionCommitted)
+            },
+            ControllerMessage::Abort => {
+                self.transaction_open = false;
+                handler.on_abort()?;
+                None
+            },
+            ControllerMessage::GetLimit(param) => Some(RemoteMessage::LimitResult(handler.on_get_limit(param)?)),
+            ControllerMessage::SetLimit(limit) => { handler.on_set_limit(limit)?; Some(RemoteMessage::LimitResult(limit)) },
+            ControllerMessage::EngineeringUnlock { pin } => { handler.on_engineering_unlock(pin)?; Some(RemoteMessage::EngineeringUnlocked) },
+            ControllerMessage::EngineeringLock => { handler.on_engineering_lock()?; Some(RemoteMessage::EngineeringLocked) },
+            ControllerMessage::ClockSyncRequest { controller_time } => {
+                let time = handler.on_clock_sync_request()?;
+                Some(RemoteMessage::ClockSyncResponse { controller_time, receive_time: time, send_time: time })
+            },
+            ControllerMessage::RunAt { timestamp } => { handler.on_run_at(timestamp)?; None },
+            ControllerMessage::FireSingle => { handler.on_fire_single()?; None },
+            ControllerMessage::FireBurst { count } => { handler.on_fire_burst(count)?; None },
+            ControllerMessage::RunFor { duration_ms } => { handler.on_run_for(duration_ms)?; None },
+            ControllerMessage::NoteOn { note, velocity } => { handler.on_note_on(note, velocity)?; None },
+            ControllerMessage::NoteOff { note } => { handler.on_note_off(note)?; None },
+            ControllerMessage::AllNotesOff => { handler.on_all_notes_off()?; None },
+            ControllerMessage::BeginRampUpload { point_count } => { handler.on_begin_ramp_upload(point_count)?; None },
+            ControllerMessage::UploadRampPoint { index, point } => { handler.on_upload_ramp_point(index, point)?; None },
+            ControllerMessage::CommitRampUpload => {
+                Some(RemoteMessage::RampProfileCommitted { point_count: handler.on_commit_ramp_upload()? })
+            },
+            ControllerMessage::Ping(sequence) => {
+                handler.on_ping(sequence);
+                Some(RemoteMessage::Ping(sequence))
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InterlockRule, Interlocks, SerialBuffer};
+
+    const PIN: u32 = 1234;
+
+    const RULES: [InterlockRule; 1] = [InterlockRule::MaxDutyCycle(0.01)];
+
+    struct Remote {
+        store: ParameterStore,
+        transaction: Transaction,
+        limits: LimitProfile,
+    }
+
+    impl RemoteHandler for Remote {
+        fn on_emergency_stop(&mut self) {}
+
+        fn parameter_store(&mut self) -> Option<&mut ParameterStore> {
+            Some(&mut self.store)
+        }
+
+        fn transaction(&mut self) -> Option<&mut Transaction> {
+            Some(&mut self.transaction)
+        }
+
+        fn limit_profile(&mut self) -> Option<&mut LimitProfile> {
+            Some(&mut self.limits)
+        }
+
+        fn validate_parameters(&mut self, set: &ParameterSet) -> Result<(), RejectReason> {
+            Interlocks::new(&RULES).check(set)
+        }
+
+        fn on_run(&mut self) -> Result<(), RejectReason> {
+            Ok(())
+        }
+
+        fn on_stop(&mut self) -> Result<(), RejectReason> {
+            Ok(())
+        }
+    }
+
+    fn dispatcher() -> Dispatcher<Remote> {
+        Dispatcher::new(Remote {
+            store: ParameterStore::new(),
+            transaction: Transaction::new(),
+            limits: LimitProfile::new(PIN, 3, 60_000),
+        })
+    }
+
+    fn poll_one(dispatcher: &mut Dispatcher<Remote>, message: ControllerMessage) -> (Result<Option<ControllerMessage>, RejectReason>, Option<RemoteMessage>) {
+        let mut rx_buffer = SerialBuffer::<32>::new();
+        let mut tx_buffer = SerialBuffer::<32>::new();
+        message.try_send(&mut rx_buffer);
+        let received = dispatcher.poll(&mut rx_buffer, &mut tx_buffer);
+        (received, RemoteMessage::try_receive(&mut tx_buffer).unwrap())
+    }
+
+    #[test]
+    fn interlocks_refuse_a_single_set_param() {
+        let mut dispatcher = dispatcher();
+        let (received, reply) = poll_one(&mut dispatcher, ControllerMessage::SetParam(ParameterValue::OffTimeMs(10)));
+        assert!(matches!(received, Err(RejectReason::InterlockViolation)));
+        assert!(matches!(reply, Some(RemoteMessage::CommandRejected { reason: RejectReason::InterlockViolation, .. })));
+        assert_eq!(dispatcher.handler().store.off_time_ms(), ParameterSet::DEFAULT.off_time_ms);
+    }
+
+    #[test]
+    fn interlocks_check_a_transaction_as_a_whole_on_commit() {
+        let mut dispatcher = dispatcher();
+        assert!(poll_one(&mut dispatcher, ControllerMessage::BeginTransaction).0.is_ok());
+        // Breaks the duty cycle rule on its own, but not together with the shorter on time
+        let (received, reply) = poll_one(&mut dispatcher, ControllerMessage::SetParam(ParameterValue::OffTimeMs(10)));
+        assert!(received.is_ok() && reply.is_none());
+        assert!(poll_one(&mut dispatcher, ControllerMessage::SetParam(ParameterValue::OnTimeUs(100))).0.is_ok());
+        let (_, reply) = poll_one(&mut dispatcher, ControllerMessage::Commit);
+        assert!(matches!(reply, Some(RemoteMessage::TransactionCommitted)));
+        assert_eq!((dispatcher.handler().store.off_time_ms(), dispatcher.handler().store.on_time_us()), (10, 100));
+
+        assert!(poll_one(&mut dispatcher, ControllerMessage::BeginTransaction).0.is_ok());
+        assert!(poll_one(&mut dispatcher, ControllerMessage::SetParam(ParameterValue::OnTimeUs(1000))).0.is_ok());
+        let (received, _) = poll_one(&mut dispatcher, ControllerMessage::Commit);
+        assert!(matches!(received, Err(RejectReason::InterlockViolation)));
+        assert_eq!(dispatcher.handler().store.on_time_us(), 100);
+        assert!(!dispatcher.is_transaction_open() && !dispatcher.handler().transaction.is_open());
+    }
+
+    #[test]
+    fn dispatch_answers_emergency_stop_without_validating_it() {
+        let mut rx_buffer = SerialBuffer::<32>::new();
+        let mut tx_buffer = SerialBuffer::<32>::new();
+        ControllerMessage::EmergencyStop.try_send(&mut rx_buffer);
+        let received = dispatch(&mut rx_buffer, &mut tx_buffer, false, |_| Err(RejectReason::InterlockViolation));
+        assert!(matches!(received, Ok(Some(ControllerMessage::EmergencyStop))));
+        assert!(matches!(RemoteMessage::try_receive(&mut tx_buffer), Ok(Some(RemoteMessage::EmergencyStopAck))));
+    }
+
+    #[test]
+    fn dispatch_addressed_answers_addressed_emergency_stops_only() {
+        let filter = AddressFilter::new(3);
+        let mut rx_buffer = SerialBuffer::<32>::new();
+        let mut tx_buffer = SerialBuffer::<32>::new();
+        ControllerMessage::EmergencyStop.try_send(&mut rx_buffer);
+        ControllerMessage::EmergencyStop.try_send_to(3, &mut rx_buffer);
+        for _ in 0..2 {
+            let received = dispatch_addressed(&filter, &mut rx_buffer, &mut tx_buffer, false, |_| Ok(()));
+            assert!(matches!(received, Ok(Some(ControllerMessage::EmergencyStop))));
+        }
+        assert!(matches!(RemoteMessage::try_receive_from(&mut tx_buffer), Ok(Some((3, RemoteMessage::EmergencyStopAck)))));
+        assert_eq!(tx_buffer.count(), 0);
+    }
+
+    #[test]
+    fn rejected_broadcasts_are_not_answered() {
+        let filter = AddressFilter::new(3);
+        let mut rx_buffer = SerialBuffer::<32>::new();
+        let mut tx_buffer = SerialBuffer::<32>::new();
+        // A broadcast `GetParam` for an unknown parameter, then the same addressed to this remote
+        rx_buffer.extend_from_slice(&[0xF0, 0x7F, 0x81, 0x60, 0xF0, 0x03, 0x81, 0x60]);
+        let received = dispatch_addressed(&filter, &mut rx_buffer, &mut tx_buffer, false, |_| Ok(()));
+        assert!(matches!(received, Err(RejectReason::UnknownParameter)));
+        assert_eq!(tx_buffer.count(), 0);
+
+        let mut dispatcher = dispatcher();
+        let received = dispatcher.poll_addressed(&filter, &mut rx_buffer, &mut tx_buffer);
+        assert!(matches!(received, Err(RejectReason::UnknownParameter)));
+        assert!(matches!(
+            RemoteMessage::try_receive_from(&mut tx_buffer),
+            Ok(Some((3, RemoteMessage::CommandRejected { reason: RejectReason::UnknownParameter, .. }))),
+        ));
+    }
+
+    fn cap_on_time(dispatcher: &mut Dispatcher<Remote>, max_us: u16) {
+        let limit = ParameterLimit { min: ParameterValue::OnTimeUs(0), max: ParameterValue::OnTimeUs(max_us) };
+        let (_, reply) = poll_one(dispatcher, ControllerMessage::SetLimit(limit));
+        assert!(matches!(reply, Some(RemoteMessage::LimitResult(result)) if result == limit));
+    }
+
+    #[test]
+    fn limits_can_only_be_changed_once_unlocked() {
+        let mut dispatcher = dispatcher();
+        let limit = ParameterLimit { min: ParameterValue::OnTimeUs(0), max: ParameterValue::OnTimeUs(500) };
+        let (received, _) = poll_one(&mut dispatcher, ControllerMessage::SetLimit(limit));
+        assert!(matches!(received, Err(RejectReason::Locked)));
+        let (received, _) = poll_one(&mut dispatcher, ControllerMessage::EngineeringUnlock { pin: PIN + 1 });
+        assert!(matches!(received, Err(RejectReason::WrongPin)));
+
+        let (_, reply) = poll_one(&mut dispatcher, ControllerMessage::EngineeringUnlock { pin: PIN });
+        assert!(matches!(reply, Some(RemoteMessage::EngineeringUnlocked)));
+        cap_on_time(&mut dispatcher, 500);
+        let (_, reply) = poll_one(&mut dispatcher, ControllerMessage::GetLimit(Parameter::OnTime));
+        assert!(matches!(reply, Some(RemoteMessage::LimitResult(result)) if result == limit));
+
+        let (_, reply) = poll_one(&mut dispatcher, ControllerMessage::EngineeringLock);
+        assert!(matches!(reply, Some(RemoteMessage::EngineeringLocked)));
+        let (received, _) = poll_one(&mut dispatcher, ControllerMessage::SetLimit(limit));
+        assert!(matches!(received, Err(RejectReason::Locked)));
+    }
+
+    #[test]
+    fn caps_are_enforced_on_set_param_and_commit() {
+        let mut dispatcher = dispatcher();
+        assert!(poll_one(&mut dispatcher, ControllerMessage::EngineeringUnlock { pin: PIN }).0.is_ok());
+        cap_on_time(&mut dispatcher, 500);
+
+        let (received, _) = poll_one(&mut dispatcher, ControllerMessage::SetParam(ParameterValue::OnTimeUs(600)));
+        assert!(matches!(received, Err(RejectReason::OutOfRange)));
+        let (_, reply) = poll_one(&mut dispatcher, ControllerMessage::SetParam(ParameterValue::OnTimeUs(400)));
+        assert!(matches!(reply, Some(RemoteMessage::ParamApplied(ParameterValue::OnTimeUs(400)))));
+
+        // Lowered while the value is staged
+        assert!(poll_one(&mut dispatcher, ControllerMessage::BeginTransaction).0.is_ok());
+        assert!(poll_one(&mut dispatcher, ControllerMessage::SetParam(ParameterValue::OnTimeUs(300))).0.is_ok());
+        cap_on_time(&mut dispatcher, 200);
+        let (received, _) = poll_one(&mut dispatcher, ControllerMessage::Commit);
+        assert!(matches!(received, Err(RejectReason::OutOfRange)));
+    }
+
+    #[test]
+    fn lowering_a_cap_brings_the_value_in_effect_within_it() {
+        let mut dispatcher = dispatcher();
+        assert!(poll_one(&mut dispatcher, ControllerMessage::EngineeringUnlock { pin: PIN }).0.is_ok());
+        assert_eq!(dispatcher.handler_mut().store.take_changes().count(), 0);
+        cap_on_time(&mut dispatcher, 200);
+        assert_eq!(dispatcher.handler().store.on_time_us(), 200);
+        assert!(dispatcher.handler().store.is_changed(Parameter::OnTime));
+    }
+
+    #[test]
+    fn set_param_is_answered_with_the_value_as_applied() {
+        let mut dispatcher = dispatcher();
+        // The wire carries sixteenths of a kHz
+        let (received, reply) = poll_one(&mut dispatcher, ControllerMessage::SetParam(ParameterValue::StartupFrequencykHz(400.03)));
+        assert!(matches!(received, Ok(Some(ControllerMessage::SetParam(ParameterValue::StartupFrequencykHz(400.0))))));
+        assert!(matches!(reply, Some(RemoteMessage::ParamApplied(ParameterValue::StartupFrequencykHz(400.0)))));
+        assert_eq!(dispatcher.handler().store.startup_frequency_khz(), 400.0);
+
+        let (_, reply) = poll_one(&mut dispatcher, ControllerMessage::GetParam(Parameter::StartupFrequency));
+        assert!(matches!(reply, Some(RemoteMessage::GetParamResult(ParameterValue::StartupFrequencykHz(400.0)))));
+    }
+
+    #[test]
+    fn staged_values_are_only_confirmed_by_the_commit() {
+        let mut dispatcher = dispatcher();
+        assert!(poll_one(&mut dispatcher, ControllerMessage::BeginTransaction).0.is_ok());
+        assert!(dispatcher.is_transaction_open());
+        let (received, reply) = poll_one(&mut dispatcher, ControllerMessage::SetParam(ParameterValue::OnTimeUs(200)));
+        assert!(matches!(received, Ok(Some(_))) && reply.is_none());
+        assert_eq!(dispatcher.handler().store.on_time_us(), ParameterSet::DEFAULT.on_time_us);
+
+        let (_, reply) = poll_one(&mut dispatcher, ControllerMessage::Abort);
+        assert!(reply.is_none() && !dispatcher.is_transaction_open());
+        assert_eq!(dispatcher.handler().store.on_time_us(), ParameterSet::DEFAULT.on_time_us);
+        let (_, reply) = poll_one(&mut dispatcher, ControllerMessage::SetParam(ParameterValue::OnTimeUs(200)));
+        assert!(matches!(reply, Some(RemoteMessage::ParamApplied(ParameterValue::OnTimeUs(200)))));
+    }
+
+    #[test]
+    fn pings_are_echoed_and_unimplemented_commands_refused() {
+        let mut dispatcher = dispatcher();
+        let (_, reply) = poll_one(&mut dispatcher, ControllerMessage::Ping(0x0ABC_DEF0));
+        assert!(matches!(reply, Some(RemoteMessage::Ping(0x0ABC_DEF0))));
+
+        let (received, reply) = poll_one(&mut dispatcher, ControllerMessage::FireSingle);
+        assert!(matches!(received, Err(RejectReason::UnknownMessage)));
+        assert!(matches!(
+            reply,
+            Some(RemoteMessage::CommandRejected { message_id, reason: RejectReason::UnknownMessage })
+                if message_id == ControllerMessage::FireSingle.message_id(),
+        ));
+
+        let (received, reply) = poll_one(&mut dispatcher, ControllerMessage::Run);
+        assert!(matches!(received, Ok(Some(ControllerMessage::Run))) && reply.is_none());
+    }
+
+    #[cfg(feature = "auth")]
+    #[test]
+    fn poll_authenticated_only_accepts_authenticated_frames() {
+        const KEY: [u8; 16] = *b"0123456789abcdef";
+        let mut controller = Authenticator::new(KEY);
+        let mut remote = Authenticator::new(KEY);
+        let mut dispatcher = dispatcher();
+        let mut rx_buffer = SerialBuffer::<64>::new();
+        let mut tx_buffer = SerialBuffer::<64>::new();
+
+        assert!(controller.try_send(&ControllerMessage::SetParam(ParameterValue::OnTimeUs(200)), &mut rx_buffer));
+        let received = dispatcher.poll_authenticated(&mut remote, &mut rx_buffer, &mut tx_buffer);
+        assert!(matches!(received, Ok(Some(ControllerMessage::SetParam(ParameterValue::OnTimeUs(200))))));
+        assert!(matches!(RemoteMessage::try_receive(&mut tx_buffer), Ok(Some(RemoteMessage::ParamApplied(ParameterValue::OnTimeUs(200))))));
+
+        assert!(ControllerMessage::SetParam(ParameterValue::OnTimeUs(300)).try_send(&mut rx_buffer));
+        let received = dispatcher.poll_authenticated(&mut remote, &mut rx_buffer, &mut tx_buffer);
+        assert!(matches!(received, Err(RejectReason::NotAuthenticated)));
+        assert!(matches!(
+            RemoteMessage::try_receive(&mut tx_buffer),
+            Ok(Some(RemoteMessage::CommandRejected { reason: RejectReason::NotAuthenticated, .. })),
+        ));
+        assert_eq!(dispatcher.handler().store.on_time_us(), 200);
+    }
+}
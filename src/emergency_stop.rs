@@ -0,0 +1,54 @@
+use crate::{ByteSink, ControllerMessage, RemoteMessage};
+
+/// Controller side: once triggered, keeps sending `ControllerMessage::EmergencyStop` every
+/// `repeat_interval_ms` until the remote answers with `RemoteMessage::EmergencyStopAck`. For a
+/// single remote; on a shared bus use `BusEmergencyStopSender`.
+pub struct EmergencyStopSender {
+    repeat_interval_ms: u32,
+    active: bool,
+    last_sent_ms: Option<u32>,
+}
+
+impl EmergencyStopSender {
+    pub fn new(repeat_interval_ms: u32) -> Self {
+        Self {
+            repeat_interval_ms,
+            active: false,
+            last_sent_ms: None,
+        }
+    }
+
+    pub fn trigger(&mut self) {
+        self.active = true;
+        self.last_sent_ms = None;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn poll(&mut self, now_ms: u32, tx_buffer: &mut impl ByteSink) -> bool {
+        if !self.active {
+            return false;
+        }
+        let due = match self.last_sent_ms {
+            Some(last_sent_ms) => now_ms.wrapping_sub(last_sent_ms) >= self.repeat_interval_ms,
+            None => true,
+        };
+        if due && ControllerMessage::EmergencyStop.try_send(tx_buffer) {
+            self.last_sent_ms = Some(now_ms);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn handle(&mut self, message: &RemoteMessage) -> bool {
+        if let RemoteMessage::EmergencyStopAck = message {
+            self.active = false;
+            true
+        } else {
+            false
+        }
+    }
+}
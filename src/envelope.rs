@@ -0,0 +1,166 @@
+use core::fmt::Write;
+
+use crate::{ParameterSet, RampPoint, RunMode};
+use crate::ramp_profile::ramp_power_at;
+
+/// Part of a bang period
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BangPhase {
+    /// Driven open loop at `StartupFrequencykHz` for `StartupTimeUs`
+    Startup,
+    /// Closed loop, waiting up to `LockTimeUs` for the current to pass `MinLockCurrentA`
+    Lock,
+    /// Closed loop, power going from `RampStartPower` to `RampEndPower` or along the ramp profile
+    Ramp,
+    /// Closed or open loop at `FlatPower`
+    Flat,
+    /// Between bangs, for `OffTimeMs`
+    Off,
+}
+
+impl BangPhase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Startup => "startup",
+            Self::Lock    => "lock",
+            Self::Ramp    => "ramp",
+            Self::Flat    => "flat",
+            Self::Off     => "off",
+        }
+    }
+}
+
+/// One point of an `Envelope`'s time series
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EnvelopeSample {
+    pub time_us: f32,
+    pub phase: BangPhase,
+    pub power: f32,
+}
+
+/// The expected drive of one bang period for a parameter set, for previewing it before firing.
+/// Time 0 is the start of the bang; the bang lasts `OnTimeUs` and is followed by `OffTimeMs` off.
+///
+/// How the on time is split depends on the run mode:
+/// - `OpenLoop` and `Interrupter`: flat at `FlatPower` throughout
+/// - `TestClosedLoop`: startup, lock window, then flat, all at `FlatPower`
+/// - `ClosedLoopRamp`: startup and lock window at `RampStartPower`, then a linear ramp reaching
+///   `RampEndPower` at the end of the bang
+/// - `CustomRamp`: startup, lock window and ramp all follow the ramp profile points
+#[derive(Copy, Clone, Debug)]
+pub struct Envelope<'a> {
+    set: ParameterSet,
+    ramp: &'a [RampPoint],
+}
+
+impl<'a> Envelope<'a> {
+    /// `ramp` is only used in `RunMode::CustomRamp`
+    pub fn new(set: &ParameterSet, ramp: &'a [RampPoint]) -> Self {
+        Self {
+            set: *set,
+            ramp,
+        }
+    }
+
+    pub fn parameters(&self) -> &ParameterSet {
+        &self.set
+    }
+
+    fn on_time_us(&self) -> f32 {
+        self.set.on_time_us as f32
+    }
+
+    fn lock_start_us(&self) -> f32 {
+        (self.set.startup_time_us as f32).min(self.on_time_us())
+    }
+
+    fn lock_end_us(&self) -> f32 {
+        (self.set.startup_time_us as f32 + self.set.lock_time_us as f32).min(self.on_time_us())
+    }
+
+    /// On time plus off time
+    pub fn bang_period_us(&self) -> f32 {
+        self.on_time_us() + self.set.off_time_ms as f32 * 1000.0
+    }
+
+    pub fn duty_cycle(&self) -> f32 {
+        self.set.duty_cycle()
+    }
+
+    pub fn phase_at(&self, time_us: f32) -> BangPhase {
+        if time_us < 0.0 || time_us >= self.on_time_us() {
+            return BangPhase::Off;
+        }
+        match self.set.run_mode {
+            RunMode::OpenLoop | RunMode::Interrupter => BangPhase::Flat,
+            RunMode::TestClosedLoop | RunMode::ClosedLoopRamp | RunMode::CustomRamp => {
+                if time_us < self.lock_start_us() {
+                    BangPhase::Startup
+                } else if time_us < self.lock_end_us() {
+                    BangPhase::Lock
+                } else if self.set.run_mode == RunMode::TestClosedLoop {
+                    BangPhase::Flat
+                } else {
+                    BangPhase::Ramp
+                }
+            },
+        }
+    }
+
+    /// Drive power setpoint `time_us` into the bang period, 0 while off
+    pub fn power_at(&self, time_us: f32) -> f32 {
+        if self.phase_at(time_us) == BangPhase::Off {
+            return 0.0;
+        }
+        match self.set.run_mode {
+            RunMode::OpenLoop | RunMode::Interrupter | RunMode::TestClosedLoop => self.set.flat_power,
+            RunMode::ClosedLoopRamp => {
+                let ramp_start_us = self.lock_end_us();
+                let ramp_length_us = self.on_time_us() - ramp_start_us;
+                if time_us < ramp_start_us || ramp_length_us <= 0.0 {
+                    self.set.ramp_start_power
+                } else {
+                    let fraction = (time_us - ramp_start_us) / ramp_length_us;
+                    self.set.ramp_start_power + (self.set.ramp_end_power - self.set.ramp_start_power) * fraction
+                }
+            },
+            RunMode::CustomRamp => ramp_power_at(self.ramp, time_us),
+        }
+    }
+
+    /// Mean power setpoint over the on time
+    pub fn mean_bang_power(&self) -> f32 {
+        let on_time_us = self.set.on_time_us;
+        if on_time_us == 0 {
+            return 0.0;
+        }
+        // Midpoint rule at 1 µs, exact for the linear segments and steps a bang is made of
+        let total: f32 = (0..on_time_us).map(|time_us| self.power_at(time_us as f32 + 0.5)).sum();
+        total / on_time_us as f32
+    }
+
+    /// Estimated mean power setpoint over the whole bang period, off time included
+    pub fn average_power(&self) -> f32 {
+        self.mean_bang_power() * self.duty_cycle()
+    }
+
+    /// Samples every `step_us` through the on time, then one more at the end of the off time
+    pub fn samples(&self, step_us: f32) -> impl Iterator<Item = EnvelopeSample> + '_ {
+        let on_time_us = self.on_time_us();
+        let steps = if step_us > 0.0 { (on_time_us / step_us) as u32 } else { 0 };
+        (0..=steps)
+            .map(move |i| i as f32 * step_us)
+            .chain(core::iter::once(on_time_us).filter(move |_| steps as f32 * step_us < on_time_us))
+            .chain(core::iter::once(self.bang_period_us()).filter(move |period_us| *period_us > on_time_us))
+            .map(|time_us| EnvelopeSample { time_us, phase: self.phase_at(time_us), power: self.power_at(time_us) })
+    }
+
+    /// Writes `samples(step_us)` as CSV with a `time_us,phase,power` header
+    pub fn write_csv<W: Write>(&self, step_us: f32, out: &mut W) -> core::fmt::Result {
+        writeln!(out, "time_us,phase,power")?;
+        for sample in self.samples(step_us) {
+            writeln!(out, "{},{},{}", sample.time_us, sample.phase.name(), sample.power)?;
+        }
+        Ok(())
+    }
+}
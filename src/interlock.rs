@@ -0,0 +1,67 @@
+use crate::{ControllerMessage, ParameterSet, ParameterValue, RejectReason};
+
+/// A limit on a combination of parameters
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InterlockRule {
+    /// See `ParameterSet::duty_cycle`
+    MaxDutyCycle(f32),
+    /// See `ParameterSet::energy_amp_microseconds`
+    MaxEnergyAmpMicroseconds(f32),
+    MaxOnTimeUs(u16),
+    MinOffTimeMs(u16),
+}
+
+impl InterlockRule {
+    pub fn is_satisfied_by(&self, set: &ParameterSet) -> bool {
+        match self {
+            Self::MaxDutyCycle(max_duty_cycle) => set.duty_cycle() <= *max_duty_cycle,
+            Self::MaxEnergyAmpMicroseconds(max_energy) => set.energy_amp_microseconds() <= *max_energy,
+            Self::MaxOnTimeUs(max_on_time_us) => set.on_time_us <= *max_on_time_us,
+            Self::MinOffTimeMs(min_off_time_ms) => set.off_time_ms >= *min_off_time_ms,
+        }
+    }
+}
+
+/// The interlock rules of one installation. Used by the remote to refuse parameter changes, and
+/// by the controller to check them before sending.
+#[derive(Copy, Clone, Debug)]
+pub struct Interlocks<'a> {
+    rules: &'a [InterlockRule],
+}
+
+impl<'a> Interlocks<'a> {
+    pub const fn new(rules: &'a [InterlockRule]) -> Self {
+        Self { rules }
+    }
+
+    pub fn rules(&self) -> &'a [InterlockRule] {
+        self.rules
+    }
+
+    /// The first rule `set` breaks, if any
+    pub fn violation(&self, set: &ParameterSet) -> Option<InterlockRule> {
+        self.rules.iter().find(|rule| !rule.is_satisfied_by(set)).copied()
+    }
+
+    pub fn check(&self, set: &ParameterSet) -> Result<(), RejectReason> {
+        match self.violation(set) {
+            Some(..) => Err(RejectReason::InterlockViolation),
+            None => Ok(()),
+        }
+    }
+
+    pub fn check_set_param(&self, current: &ParameterSet, value: ParameterValue) -> Result<(), RejectReason> {
+        self.check(&current.with(value))
+    }
+
+    /// Checks a `SetParam` against `current`; every other message passes. Fits the `validate`
+    /// argument of `dispatch`, given the same `transaction_open`. A `SetParam` staged in an open
+    /// transaction passes too, since the intermediate sets may legitimately break a rule; check
+    /// the whole set on commit instead, e.g. `transaction.commit(current, |set| interlocks.check(set))`.
+    pub fn check_message(&self, current: &ParameterSet, transaction_open: bool, message: &ControllerMessage) -> Result<(), RejectReason> {
+        match message {
+            ControllerMessage::SetParam(value) if !transaction_open => self.check_set_param(current, *value),
+            _ => Ok(()),
+        }
+    }
+}
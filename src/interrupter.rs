@@ -0,0 +1,165 @@
+use core::f32::consts::SQRT_2;
+
+use crate::ControllerMessage;
+
+/// Remote side settings for `RunMode::Interrupter`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InterrupterConfig {
+    /// `FlatPower` used for a note with velocity 1
+    pub min_power: f32,
+    /// `FlatPower` used for a note with velocity 127
+    pub max_power: f32,
+    /// Highest fraction of time spent firing, over all voices together. Must be above 0.
+    pub max_duty_cycle: f32,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Voice {
+    note: u8,
+    velocity: u8,
+    period_us: u32,
+    next_bang_us: u32,
+    started: u32,
+}
+
+/// 2^(i/12) for each semitone of an octave
+const SEMITONE_RATIOS: [f32; 12] = [
+    1.0, 1.059_463, 1.122_462, 1.189_207, 1.259_921, 1.334_84,
+    SQRT_2, 1.498_307, 1.587_401, 1.681_793, 1.781_797, 1.887_749,
+];
+
+/// Equal tempered frequency of a MIDI note, with note 69 at 440 Hz
+pub fn note_frequency_hz(note: u8) -> f32 {
+    let semitones = note as i32 - 69;
+    let mut frequency = 440.0 * SEMITONE_RATIOS[semitones.rem_euclid(12) as usize];
+    let octaves = semitones.div_euclid(12);
+    for _ in 0..octaves.abs() {
+        if octaves > 0 {
+            frequency *= 2.0;
+        } else {
+            frequency *= 0.5;
+        }
+    }
+    frequency
+}
+
+/// Remote side: turns `NoteOn`, `NoteOff` and `AllNotesOff` into bang timing while in
+/// `RunMode::Interrupter`. Each held note fires bangs at its own frequency, with velocity
+/// setting the `FlatPower` of the bang. At most `VOICES` notes sound at once; a new note takes
+/// over the oldest voice when all are in use. Bangs are spaced so the duty cycle never goes over
+/// `max_duty_cycle`, so notes too high or too many at once come out late rather than too hot.
+pub struct NotePlayer<const VOICES: usize> {
+    config: InterrupterConfig,
+    voices: [Option<Voice>; VOICES],
+    started: u32,
+    next_allowed_us: Option<u32>,
+}
+
+impl<const VOICES: usize> NotePlayer<VOICES> {
+    pub fn new(config: InterrupterConfig) -> Self {
+        Self {
+            config,
+            voices: [None; VOICES],
+            started: 0,
+            next_allowed_us: None,
+        }
+    }
+
+    pub fn config(&self) -> InterrupterConfig {
+        self.config
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.voices.iter().any(Option::is_some)
+    }
+
+    pub fn notes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.voices.iter().flatten().map(|voice| voice.note)
+    }
+
+    /// Applies a note message. Returns `false` for any other message.
+    pub fn handle(&mut self, message: &ControllerMessage, now_us: u32) -> bool {
+        match message {
+            ControllerMessage::NoteOn { note, velocity } => self.note_on(*note, *velocity, now_us),
+            ControllerMessage::NoteOff { note } => self.note_off(*note),
+            ControllerMessage::AllNotesOff => self.all_notes_off(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Starts a note, firing its first bang as soon as the duty cycle allows. Velocity 0 stops
+    /// the note, as in MIDI.
+    pub fn note_on(&mut self, note: u8, velocity: u8, now_us: u32) {
+        if velocity == 0 {
+            self.note_off(note);
+            return;
+        }
+        let index = self.voices.iter().position(|voice| matches!(voice, Some(voice) if voice.note == note))
+            .or_else(|| self.voices.iter().position(Option::is_none))
+            .or_else(|| {
+                let started = self.started;
+                (0..VOICES).max_by_key(|&i| self.voices[i].map_or(0, |voice| started.wrapping_sub(voice.started)))
+            });
+        let Some(index) = index else {
+            return;
+        };
+        self.voices[index] = Some(Voice {
+            note,
+            velocity,
+            period_us: (1_000_000.0 / note_frequency_hz(note)) as u32,
+            next_bang_us: now_us,
+            started: self.started,
+        });
+        self.started = self.started.wrapping_add(1);
+    }
+
+    pub fn note_off(&mut self, note: u8) {
+        for voice in self.voices.iter_mut() {
+            if matches!(voice, Some(playing) if playing.note == note) {
+                *voice = None;
+            }
+        }
+    }
+
+    pub fn all_notes_off(&mut self) {
+        self.voices = [None; VOICES];
+    }
+
+    /// `FlatPower` for a note of the given velocity
+    pub fn power(&self, velocity: u8) -> f32 {
+        let velocity = velocity.clamp(1, 127);
+        self.config.min_power + (self.config.max_power - self.config.min_power) * (velocity - 1) as f32 / 126.0
+    }
+
+    /// Returns the `FlatPower` of the bang to fire now, if one is due. When several notes are
+    /// due at once they share a single bang at the loudest of their powers. `on_time_us` is the
+    /// length of the bang, used for the duty cycle limit.
+    pub fn poll(&mut self, now_us: u32, on_time_us: u32) -> Option<f32> {
+        if self.config.max_duty_cycle <= 0.0 {
+            return None;
+        }
+        if let Some(next_allowed_us) = self.next_allowed_us {
+            if (now_us.wrapping_sub(next_allowed_us) as i32) < 0 {
+                return None;
+            }
+            self.next_allowed_us = None;
+        }
+        let mut velocity = None;
+        for voice in self.voices.iter_mut().flatten() {
+            if (now_us.wrapping_sub(voice.next_bang_us) as i32) < 0 {
+                continue;
+            }
+            velocity = velocity.max(Some(voice.velocity));
+            voice.next_bang_us = voice.next_bang_us.wrapping_add(voice.period_us);
+            if (now_us.wrapping_sub(voice.next_bang_us) as i32) >= 0 {
+                // Fell behind by more than a period; drop the missed bangs
+                voice.next_bang_us = now_us.wrapping_add(voice.period_us);
+            }
+        }
+        let velocity = velocity?;
+        let spacing_us = (on_time_us as f32 / self.config.max_duty_cycle.min(1.0)) as u32;
+        self.next_allowed_us = Some(now_us.wrapping_add(spacing_us));
+        Some(self.power(velocity))
+    }
+}
@@ -3,6 +3,75 @@
 mod serial_buffer;
 pub use serial_buffer::*;
 
+mod decoder;
+pub use decoder::*;
+
+mod status;
+pub use status::*;
+
+mod emergency_stop;
+pub use emergency_stop::*;
+
+mod dispatcher;
+pub use dispatcher::*;
+
+mod param_apply;
+pub use param_apply::*;
+
+mod parameter_set;
+pub use parameter_set::*;
+
+mod parameter_store;
+pub use parameter_store::*;
+
+mod transaction;
+pub use transaction::*;
+
+mod interlock;
+pub use interlock::*;
+
+mod limit_profile;
+pub use limit_profile::*;
+
+mod addressing;
+pub use addressing::*;
+
+mod clock_sync;
+pub use clock_sync::*;
+
+mod run_control;
+pub use run_control::*;
+
+mod interrupter;
+pub use interrupter::*;
+
+mod midi;
+pub use midi::*;
+
+mod ramp_profile;
+pub use ramp_profile::*;
+
+mod envelope;
+pub use envelope::*;
+
+mod sequencer;
+pub use sequencer::*;
+
+#[cfg(any(feature = "embedded-io", feature = "embedded-hal-nb", feature = "embedded-io-async"))]
+mod transport;
+#[cfg(any(feature = "embedded-io", feature = "embedded-hal-nb", feature = "embedded-io-async"))]
+pub use transport::*;
+
+#[cfg(feature = "client")]
+mod client;
+#[cfg(feature = "client")]
+pub use client::*;
+
+#[cfg(feature = "auth")]
+mod auth;
+#[cfg(feature = "auth")]
+pub use auth::*;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Parameter {
     DelayCompensation,
@@ -20,7 +89,65 @@ pub enum Parameter {
     FlatPower,
 }
 
-#[derive(Copy, Clone, Debug)]
+pub const PARAMETER_COUNT: usize = 13;
+
+impl Parameter {
+    pub const ALL: [Parameter; PARAMETER_COUNT] = [
+        Self::DelayCompensation,
+        Self::StartupFrequency,
+        Self::LockRange,
+        Self::RunMode,
+        Self::LockTime,
+        Self::StartupTime,
+        Self::OnTime,
+        Self::OffTime,
+        Self::RampStartPower,
+        Self::RampEndPower,
+        Self::MinLockCurrent,
+        Self::CurrentLimit,
+        Self::FlatPower,
+    ];
+
+    /// Position of this parameter in `Parameter::ALL`
+    pub fn index(&self) -> usize {
+        match self {
+            Self::DelayCompensation => 0,
+            Self::StartupFrequency  => 1,
+            Self::LockRange         => 2,
+            Self::RunMode           => 3,
+            Self::LockTime          => 4,
+            Self::StartupTime       => 5,
+            Self::OnTime            => 6,
+            Self::OffTime           => 7,
+            Self::RampStartPower    => 8,
+            Self::RampEndPower      => 9,
+            Self::MinLockCurrent    => 10,
+            Self::CurrentLimit      => 11,
+            Self::FlatPower         => 12,
+        }
+    }
+
+    /// Smallest step the wire encoding can represent, in the units of the matching `ParameterValue`
+    pub fn resolution(&self) -> f32 {
+        match self {
+            Self::DelayCompensation => 1.0,
+            Self::StartupFrequency  => 1.0 / 16.0,
+            Self::LockRange         => 1.0 / 16.0,
+            Self::RunMode           => 0.0,
+            Self::LockTime          => 1.0,
+            Self::StartupTime       => 1.0,
+            Self::OnTime            => 10.0,
+            Self::OffTime           => 1.0,
+            Self::RampStartPower    => 1.0 / 16383.0,
+            Self::RampEndPower      => 1.0 / 16383.0,
+            Self::MinLockCurrent    => 1.0 / 256.0,
+            Self::CurrentLimit      => 1.0 / 32.0,
+            Self::FlatPower         => 1.0 / 16383.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ParameterValue {
     DelayCompensationNS(i16),
     StartupFrequencykHz(f32),
@@ -40,7 +167,7 @@ pub enum ParameterValue {
 impl ParameterValue {
     pub fn parameter(&self) -> Parameter {
         match self {
-            Self::DelayCompensationNS(..) => Parameter::CurrentLimit,
+            Self::DelayCompensationNS(..) => Parameter::DelayCompensation,
             Self::StartupFrequencykHz(..) => Parameter::StartupFrequency,
             Self::LockRangekHz(..) => Parameter::LockRange,
             Self::RunMode(..) => Parameter::RunMode,
@@ -55,6 +182,24 @@ impl ParameterValue {
             Self::FlatPower(..) => Parameter::FlatPower,
         }
     }
+
+    pub(crate) fn as_f32(&self) -> f32 {
+        match self {
+            Self::DelayCompensationNS(delay_ns) => *delay_ns as f32,
+            Self::StartupFrequencykHz(frequency_khz) => *frequency_khz,
+            Self::LockRangekHz(frequency_khz) => *frequency_khz,
+            Self::RunMode(run_mode) => Into::<u16>::into(*run_mode) as f32,
+            Self::LockTimeUs(time) => *time as f32,
+            Self::StartupTimeUs(time) => *time as f32,
+            Self::OnTimeUs(time) => *time as f32,
+            Self::OffTimeMs(time) => *time as f32,
+            Self::RampStartPower(power) => *power,
+            Self::RampEndPower(power) => *power,
+            Self::MinLockCurrentA(current) => *current,
+            Self::CurrentLimitA(current) => *current,
+            Self::FlatPower(power) => *power,
+        }
+    }
 }
 
 fn sign_extend_i14(x: u16) -> i16 {
@@ -76,7 +221,7 @@ impl TryFrom<(Parameter, u16)> for ParameterValue {
             Parameter::RunMode => Self::RunMode(RunMode::try_from(value)?),
             Parameter::LockTime => Self::LockTimeUs(value),
             Parameter::StartupTime => Self::StartupTimeUs(value),
-            Parameter::OnTime => Self::OnTimeUs(value * 10),
+            Parameter::OnTime => Self::OnTimeUs(value.checked_mul(10).ok_or(())?),
             Parameter::OffTime => Self::OffTimeMs(value),
             Parameter::RampStartPower => Self::RampStartPower(value as f32 / 16383.0),
             Parameter::RampEndPower => Self::RampEndPower(value as f32 / 16383.0),
@@ -87,6 +232,7 @@ impl TryFrom<(Parameter, u16)> for ParameterValue {
     }
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<(Parameter, u16)> for ParameterValue {
     fn into(self) -> (Parameter, u16) {
         match self {
@@ -107,19 +253,24 @@ impl Into<(Parameter, u16)> for ParameterValue {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum RunMode {
     OpenLoop,
     TestClosedLoop,
     ClosedLoopRamp,
+    Interrupter,
+    CustomRamp,
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<u16> for RunMode {
     fn into(self) -> u16 {
         match self {
             Self::OpenLoop        => 0,
             Self::TestClosedLoop  => 1,
             Self::ClosedLoopRamp  => 2,
+            Self::Interrupter     => 3,
+            Self::CustomRamp      => 4,
         }
     }
 }
@@ -131,6 +282,8 @@ impl TryFrom<u16> for RunMode {
             0 => Self::OpenLoop,
             1 => Self::TestClosedLoop,
             2 => Self::ClosedLoopRamp,
+            3 => Self::Interrupter,
+            4 => Self::CustomRamp,
             _ => return Err(()),
         })
     }
@@ -150,6 +303,7 @@ const PARAMETER_ID_CURRENT_LIMIT    : u8 = 11;
 const PARAMETER_ID_FLAT_POWER       : u8 = 12;
 const PARAMETER_ID_LOCK_RANGE       : u8 = 13;
 
+#[allow(clippy::from_over_into)]
 impl Into<u8> for Parameter {
     fn into(self) -> u8 {
         match self {
@@ -186,6 +340,7 @@ impl TryFrom<u8> for Parameter {
             PARAMETER_ID_MIN_LOCK_CURRENT => Self::MinLockCurrent,
             PARAMETER_ID_CURRENT_LIMIT    => Self::CurrentLimit,
             PARAMETER_ID_FLAT_POWER       => Self::FlatPower,
+            PARAMETER_ID_LOCK_RANGE       => Self::LockRange,
             _ => return Err(())
         })
     }
@@ -206,6 +361,7 @@ pub enum StatisticValue {
     FeedbackFrequencykHz(f32),
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<(Statistic, u16)> for StatisticValue {
     fn into(self) -> (Statistic, u16) {
         match self {
@@ -226,6 +382,7 @@ impl TryFrom<(Statistic, u16)> for StatisticValue {
     }
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<u8> for Statistic {
     fn into(self) -> u8 {
         match self {
@@ -246,8 +403,112 @@ impl TryFrom<u8> for Statistic {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    Malformed,
+    UnknownMessage,
+    UnknownParameter,
+    UnknownStatistic,
+    OutOfRange,
+    NotAllowedWhileRunning,
+    NoTransaction,
+    InterlockViolation,
+    Locked,
+    WrongPin,
+    LockedOut,
+    NotAuthenticated,
+    TooLate,
+    InvalidRampProfile,
+}
+
+const REJECT_REASON_ID_MALFORMED                 : u8 = 0;
+const REJECT_REASON_ID_UNKNOWN_MESSAGE           : u8 = 1;
+const REJECT_REASON_ID_UNKNOWN_PARAMETER         : u8 = 2;
+const REJECT_REASON_ID_UNKNOWN_STATISTIC         : u8 = 3;
+const REJECT_REASON_ID_OUT_OF_RANGE              : u8 = 4;
+const REJECT_REASON_ID_NOT_ALLOWED_WHILE_RUNNING : u8 = 5;
+const REJECT_REASON_ID_NO_TRANSACTION            : u8 = 6;
+const REJECT_REASON_ID_INTERLOCK_VIOLATION       : u8 = 7;
+const REJECT_REASON_ID_LOCKED                    : u8 = 8;
+const REJECT_REASON_ID_WRONG_PIN                 : u8 = 9;
+const REJECT_REASON_ID_LOCKED_OUT                : u8 = 10;
+const REJECT_REASON_ID_NOT_AUTHENTICATED         : u8 = 11;
+const REJECT_REASON_ID_TOO_LATE                  : u8 = 12;
+const REJECT_REASON_ID_INVALID_RAMP_PROFILE      : u8 = 13;
+
+#[allow(clippy::from_over_into)]
+impl Into<u8> for RejectReason {
+    fn into(self) -> u8 {
+        match self {
+            Self::Malformed              => REJECT_REASON_ID_MALFORMED,
+            Self::UnknownMessage         => REJECT_REASON_ID_UNKNOWN_MESSAGE,
+            Self::UnknownParameter       => REJECT_REASON_ID_UNKNOWN_PARAMETER,
+            Self::UnknownStatistic       => REJECT_REASON_ID_UNKNOWN_STATISTIC,
+            Self::OutOfRange             => REJECT_REASON_ID_OUT_OF_RANGE,
+            Self::NotAllowedWhileRunning => REJECT_REASON_ID_NOT_ALLOWED_WHILE_RUNNING,
+            Self::NoTransaction          => REJECT_REASON_ID_NO_TRANSACTION,
+            Self::InterlockViolation     => REJECT_REASON_ID_INTERLOCK_VIOLATION,
+            Self::Locked                 => REJECT_REASON_ID_LOCKED,
+            Self::WrongPin               => REJECT_REASON_ID_WRONG_PIN,
+            Self::LockedOut              => REJECT_REASON_ID_LOCKED_OUT,
+            Self::NotAuthenticated       => REJECT_REASON_ID_NOT_AUTHENTICATED,
+            Self::TooLate                => REJECT_REASON_ID_TOO_LATE,
+            Self::InvalidRampProfile     => REJECT_REASON_ID_INVALID_RAMP_PROFILE,
+        }
+    }
+}
+
+impl TryFrom<u8> for RejectReason {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, ()> {
+        Ok(match value {
+            REJECT_REASON_ID_MALFORMED                 => Self::Malformed,
+            REJECT_REASON_ID_UNKNOWN_MESSAGE           => Self::UnknownMessage,
+            REJECT_REASON_ID_UNKNOWN_PARAMETER         => Self::UnknownParameter,
+            REJECT_REASON_ID_UNKNOWN_STATISTIC         => Self::UnknownStatistic,
+            REJECT_REASON_ID_OUT_OF_RANGE              => Self::OutOfRange,
+            REJECT_REASON_ID_NOT_ALLOWED_WHILE_RUNNING => Self::NotAllowedWhileRunning,
+            REJECT_REASON_ID_NO_TRANSACTION            => Self::NoTransaction,
+            REJECT_REASON_ID_INTERLOCK_VIOLATION       => Self::InterlockViolation,
+            REJECT_REASON_ID_LOCKED                    => Self::Locked,
+            REJECT_REASON_ID_WRONG_PIN                 => Self::WrongPin,
+            REJECT_REASON_ID_LOCKED_OUT                => Self::LockedOut,
+            REJECT_REASON_ID_NOT_AUTHENTICATED         => Self::NotAuthenticated,
+            REJECT_REASON_ID_TOO_LATE                  => Self::TooLate,
+            REJECT_REASON_ID_INVALID_RAMP_PROFILE      => Self::InvalidRampProfile,
+            _ => return Err(())
+        })
+    }
+}
+
 const MESSAGE_START_BIT: u8 = 0x80;
 
+/// Longest frame of either message type, start byte included
+pub const MAX_FRAME_LENGTH: usize = 16;
+
+/// Most bangs one `FireBurst` can ask for, since its count is sent as two 7 bit groups
+pub const MAX_BURST_COUNT: u16 = 0x3FFF;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ends before the frame does. The first `skipped` bytes of the input come before
+    /// any frame, e.g. line noise, and can be dropped; the rest should be kept until more arrives.
+    Incomplete { skipped: usize },
+    /// The frame can't be decoded. `consumed` bytes of the input were taken up by it, so decoding
+    /// can carry on from there.
+    Invalid { consumed: usize },
+}
+
+fn push_frame(frame: &[u8], tx_buffer: &mut impl ByteSink) -> bool {
+    if tx_buffer.free_space() < frame.len() {
+        return false;
+    }
+    for byte in frame {
+        tx_buffer.push(*byte);
+    }
+    true
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum ControllerMessage {
     SetDebugLed(bool),
@@ -258,6 +519,26 @@ pub enum ControllerMessage {
     KeepAlive,
     Run,
     Stop,
+    EmergencyStop,
+    BeginTransaction,
+    Commit,
+    Abort,
+    GetLimit(Parameter),
+    SetLimit(ParameterLimit),
+    EngineeringUnlock { pin: u32 },
+    EngineeringLock,
+    ClockSyncRequest { controller_time: u32 },
+    RunAt { timestamp: u32 },
+    FireSingle,
+    /// `count` is 1..=`MAX_BURST_COUNT`
+    FireBurst { count: u16 },
+    RunFor { duration_ms: u32 },
+    NoteOn { note: u8, velocity: u8 },
+    NoteOff { note: u8 },
+    AllNotesOff,
+    BeginRampUpload { point_count: u8 },
+    UploadRampPoint { index: u8, point: RampPoint },
+    CommitRampUpload,
     Ping(u32),
 }
 
@@ -269,92 +550,373 @@ const CONTROLLER_MESSAGE_ID_RESET_STATS: u8 = 4;
 const CONTROLLER_MESSAGE_ID_KEEP_ALIVE: u8 = 5;
 const CONTROLLER_MESSAGE_ID_RUN: u8 = 6;
 const CONTROLLER_MESSAGE_ID_STOP: u8 = 7;
+const CONTROLLER_MESSAGE_ID_BEGIN_TRANSACTION: u8 = 8;
+const CONTROLLER_MESSAGE_ID_COMMIT: u8 = 9;
+const CONTROLLER_MESSAGE_ID_ABORT: u8 = 10;
+const CONTROLLER_MESSAGE_ID_GET_LIMIT: u8 = 11;
+const CONTROLLER_MESSAGE_ID_SET_LIMIT: u8 = 12;
+const CONTROLLER_MESSAGE_ID_ENGINEERING_UNLOCK: u8 = 13;
+const CONTROLLER_MESSAGE_ID_ENGINEERING_LOCK: u8 = 14;
+const CONTROLLER_MESSAGE_ID_CLOCK_SYNC_REQUEST: u8 = 16;
+const CONTROLLER_MESSAGE_ID_RUN_AT: u8 = 17;
+const CONTROLLER_MESSAGE_ID_FIRE_SINGLE: u8 = 18;
+const CONTROLLER_MESSAGE_ID_FIRE_BURST: u8 = 19;
+const CONTROLLER_MESSAGE_ID_RUN_FOR: u8 = 20;
+const CONTROLLER_MESSAGE_ID_NOTE_ON: u8 = 22;
+const CONTROLLER_MESSAGE_ID_NOTE_OFF: u8 = 23;
+const CONTROLLER_MESSAGE_ID_ALL_NOTES_OFF: u8 = 24;
+const CONTROLLER_MESSAGE_ID_BEGIN_RAMP_UPLOAD: u8 = 25;
+const CONTROLLER_MESSAGE_ID_UPLOAD_RAMP_POINT: u8 = 26;
+const CONTROLLER_MESSAGE_ID_COMMIT_RAMP_UPLOAD: u8 = 27;
+// 15 is taken by the authenticated frame wrapper, see `Authenticator`, and 0x70 by the address
+// header, see `AddressFilter`. 21 (0x15) is a bit flip away from the emergency stop id and must
+// stay unused.
 const CONTROLLER_MESSAGE_ID_PING: u8 = 0x7F;
 
+// The emergency stop frame is the start byte followed by its id repeated and bit-inverted twice,
+// and survives any single bit error:
+// - A start byte within one bit of this id is still taken as an emergency stop, so no other
+//   message id may be within a single bit flip of it.
+// - A frame that lost its start bit is still recognised from the id and the check bytes.
+// - Check bytes are compared without their top bit, and one with the start bit set doesn't cut
+//   the frame short.
+// - One wrong check byte is tolerated.
+const CONTROLLER_MESSAGE_ID_EMERGENCY_STOP: u8 = 0x55;
+const EMERGENCY_STOP_CHECK_BYTES: [u8; 4] = [
+    CONTROLLER_MESSAGE_ID_EMERGENCY_STOP,
+    !CONTROLLER_MESSAGE_ID_EMERGENCY_STOP & 0x7F,
+    CONTROLLER_MESSAGE_ID_EMERGENCY_STOP,
+    !CONTROLLER_MESSAGE_ID_EMERGENCY_STOP & 0x7F,
+];
+
+const fn controller_message_length(id: u8) -> Option<usize> {
+    Some(match id {
+        CONTROLLER_MESSAGE_ID_SET_DEBUG_LED => 2,
+        CONTROLLER_MESSAGE_ID_GET_PARAM => 2,
+        CONTROLLER_MESSAGE_ID_SET_PARAM => 4,
+        CONTROLLER_MESSAGE_ID_GET_STAT => 2,
+        CONTROLLER_MESSAGE_ID_RESET_STATS => 1,
+        CONTROLLER_MESSAGE_ID_RUN => 1,
+        CONTROLLER_MESSAGE_ID_STOP => 1,
+        CONTROLLER_MESSAGE_ID_KEEP_ALIVE => 1,
+        CONTROLLER_MESSAGE_ID_EMERGENCY_STOP => 5,
+        CONTROLLER_MESSAGE_ID_BEGIN_TRANSACTION => 1,
+        CONTROLLER_MESSAGE_ID_COMMIT => 1,
+        CONTROLLER_MESSAGE_ID_ABORT => 1,
+        CONTROLLER_MESSAGE_ID_GET_LIMIT => 2,
+        CONTROLLER_MESSAGE_ID_SET_LIMIT => 6,
+        CONTROLLER_MESSAGE_ID_ENGINEERING_UNLOCK => 6,
+        CONTROLLER_MESSAGE_ID_ENGINEERING_LOCK => 1,
+        CONTROLLER_MESSAGE_ID_CLOCK_SYNC_REQUEST => 6,
+        CONTROLLER_MESSAGE_ID_RUN_AT => 6,
+        CONTROLLER_MESSAGE_ID_FIRE_SINGLE => 1,
+        CONTROLLER_MESSAGE_ID_FIRE_BURST => 3,
+        CONTROLLER_MESSAGE_ID_RUN_FOR => 6,
+        CONTROLLER_MESSAGE_ID_NOTE_ON => 3,
+        CONTROLLER_MESSAGE_ID_NOTE_OFF => 2,
+        CONTROLLER_MESSAGE_ID_ALL_NOTES_OFF => 1,
+        CONTROLLER_MESSAGE_ID_BEGIN_RAMP_UPLOAD => 2,
+        CONTROLLER_MESSAGE_ID_UPLOAD_RAMP_POINT => 7,
+        CONTROLLER_MESSAGE_ID_COMMIT_RAMP_UPLOAD => 1,
+        CONTROLLER_MESSAGE_ID_PING => 5,
+        _ => return None,
+    })
+}
+
+fn is_emergency_stop_id(id: u8) -> bool {
+    (id ^ CONTROLLER_MESSAGE_ID_EMERGENCY_STOP).count_ones() <= 1
+}
+
+// An emergency stop frame that lost its start bit, as it would show up among skipped bytes
+const UNMARKED_EMERGENCY_STOP: [u8; 5] = [
+    CONTROLLER_MESSAGE_ID_EMERGENCY_STOP,
+    EMERGENCY_STOP_CHECK_BYTES[0],
+    EMERGENCY_STOP_CHECK_BYTES[1],
+    EMERGENCY_STOP_CHECK_BYTES[2],
+    EMERGENCY_STOP_CHECK_BYTES[3],
+];
+
+// Checks whether the bytes at the front of `rx_buffer`, which have no start byte, are an emergency
+// stop that lost its start bit, and pops it if so. Returns `None` while there aren't enough bytes
+// to tell.
+fn take_unmarked_emergency_stop(rx_buffer: &mut impl ByteSource) -> Option<bool> {
+    for (offset, expected) in UNMARKED_EMERGENCY_STOP.iter().enumerate() {
+        if rx_buffer.peek_at(offset)? != *expected {
+            return Some(false);
+        }
+    }
+    for _ in UNMARKED_EMERGENCY_STOP {
+        rx_buffer.pop();
+    }
+    Some(true)
+}
+
+// Payload bytes never have the start bit set, so one showing up before a frame is complete means
+// the rest of that frame was lost. Drops the partial frame so the new one can be decoded.
+fn drop_interrupted_frame(rx_buffer: &mut impl ByteSource, length: usize) -> bool {
+    let available = rx_buffer.count().min(length);
+    for offset in 1..available {
+        if (rx_buffer.peek_at(offset).unwrap() & MESSAGE_START_BIT) != 0 {
+            for _ in 0..offset {
+                rx_buffer.pop();
+            }
+            return true;
+        }
+    }
+    false
+}
+
 impl ControllerMessage {
-    pub fn try_send<const N: usize>(&self, buffer: &mut SerialBuffer<N>) -> bool {
-        let length = match self {
-            Self::SetDebugLed(..) => 2,
-            Self::GetParam(..)    => 2,
-            Self::SetParam(..)    => 4,
-            Self::GetStat(..)     => 2,
-            Self::ResetStats      => 1,
-            Self::KeepAlive       => 1,
-            Self::Run             => 1,
-            Self::Stop            => 1,
-            Self::Ping(..)        => 5,
-        };
-        if buffer.free_space() >= length {
-            match self {
-                Self::SetDebugLed(state) => {
-                    buffer.push(CONTROLLER_MESSAGE_ID_SET_DEBUG_LED | MESSAGE_START_BIT);
-                    buffer.push(if *state { 1 } else { 0 });
-                }
-                Self::GetParam(param) => {
-                    buffer.push(CONTROLLER_MESSAGE_ID_GET_PARAM | MESSAGE_START_BIT);
-                    buffer.push((*param).into());
-                },
-                Self::SetParam(parameter_value) => {
-                    let (param, value) = (*parameter_value).into();
-                    buffer.push(CONTROLLER_MESSAGE_ID_SET_PARAM | MESSAGE_START_BIT);
-                    buffer.push(param.into());
-                    buffer.push(((value >> 0) & 0x7F) as u8);
-                    buffer.push(((value >> 7) & 0x7F) as u8);
-                },
-                Self::GetStat(stat) => {
-                    buffer.push(CONTROLLER_MESSAGE_ID_GET_STAT | MESSAGE_START_BIT);
-                    buffer.push((*stat).into());
-                },
-                Self::ResetStats => {
-                    buffer.push(CONTROLLER_MESSAGE_ID_RESET_STATS | MESSAGE_START_BIT);
-                },
-                Self::KeepAlive => {
-                    buffer.push(CONTROLLER_MESSAGE_ID_KEEP_ALIVE | MESSAGE_START_BIT);
-                },
-                Self::Run => {
-                    buffer.push(CONTROLLER_MESSAGE_ID_RUN | MESSAGE_START_BIT);
-                },
-                Self::Stop => {
-                    buffer.push(CONTROLLER_MESSAGE_ID_STOP | MESSAGE_START_BIT);
-                },
-                Self::Ping(seq) => {
-                    buffer.push(CONTROLLER_MESSAGE_ID_PING | MESSAGE_START_BIT);
-                    buffer.push(((*seq >>  0) & 0x7F) as u8);
-                    buffer.push(((*seq >>  7) & 0x7F) as u8);
-                    buffer.push(((*seq >> 14) & 0x7F) as u8);
-                    buffer.push(((*seq >> 21) & 0x7F) as u8);
-                },
+    pub const fn message_id(&self) -> u8 {
+        match self {
+            Self::SetDebugLed(..) => CONTROLLER_MESSAGE_ID_SET_DEBUG_LED,
+            Self::GetParam(..)    => CONTROLLER_MESSAGE_ID_GET_PARAM,
+            Self::SetParam(..)    => CONTROLLER_MESSAGE_ID_SET_PARAM,
+            Self::GetStat(..)     => CONTROLLER_MESSAGE_ID_GET_STAT,
+            Self::ResetStats      => CONTROLLER_MESSAGE_ID_RESET_STATS,
+            Self::KeepAlive       => CONTROLLER_MESSAGE_ID_KEEP_ALIVE,
+            Self::Run             => CONTROLLER_MESSAGE_ID_RUN,
+            Self::Stop            => CONTROLLER_MESSAGE_ID_STOP,
+            Self::EmergencyStop   => CONTROLLER_MESSAGE_ID_EMERGENCY_STOP,
+            Self::BeginTransaction => CONTROLLER_MESSAGE_ID_BEGIN_TRANSACTION,
+            Self::Commit          => CONTROLLER_MESSAGE_ID_COMMIT,
+            Self::Abort           => CONTROLLER_MESSAGE_ID_ABORT,
+            Self::GetLimit(..)    => CONTROLLER_MESSAGE_ID_GET_LIMIT,
+            Self::SetLimit(..)    => CONTROLLER_MESSAGE_ID_SET_LIMIT,
+            Self::EngineeringUnlock { .. } => CONTROLLER_MESSAGE_ID_ENGINEERING_UNLOCK,
+            Self::EngineeringLock => CONTROLLER_MESSAGE_ID_ENGINEERING_LOCK,
+            Self::ClockSyncRequest { .. } => CONTROLLER_MESSAGE_ID_CLOCK_SYNC_REQUEST,
+            Self::RunAt { .. }    => CONTROLLER_MESSAGE_ID_RUN_AT,
+            Self::FireSingle      => CONTROLLER_MESSAGE_ID_FIRE_SINGLE,
+            Self::FireBurst { .. } => CONTROLLER_MESSAGE_ID_FIRE_BURST,
+            Self::RunFor { .. }   => CONTROLLER_MESSAGE_ID_RUN_FOR,
+            Self::NoteOn { .. }   => CONTROLLER_MESSAGE_ID_NOTE_ON,
+            Self::NoteOff { .. }  => CONTROLLER_MESSAGE_ID_NOTE_OFF,
+            Self::AllNotesOff     => CONTROLLER_MESSAGE_ID_ALL_NOTES_OFF,
+            Self::BeginRampUpload { .. } => CONTROLLER_MESSAGE_ID_BEGIN_RAMP_UPLOAD,
+            Self::UploadRampPoint { .. } => CONTROLLER_MESSAGE_ID_UPLOAD_RAMP_POINT,
+            Self::CommitRampUpload => CONTROLLER_MESSAGE_ID_COMMIT_RAMP_UPLOAD,
+            Self::Ping(..)        => CONTROLLER_MESSAGE_ID_PING,
+        }
+    }
+
+    /// Length of the encoded frame, start byte included
+    pub const fn encoded_len(&self) -> usize {
+        match Self::encoded_len_of(self.message_id()) {
+            Some(length) => length,
+            None => 0,
+        }
+    }
+
+    /// Length of the frame for `message_id`, start byte included, or `None` for an unknown id
+    pub const fn encoded_len_of(message_id: u8) -> Option<usize> {
+        controller_message_length(message_id)
+    }
+
+    /// Encodes the frame into the start of `out` and returns its length. Fails if `out` is too
+    /// short for it, or for a `FireBurst` over `MAX_BURST_COUNT`.
+    // The `<< 0` shifts are kept so every 7 bit group of a value lines up with the others
+    #[allow(clippy::identity_op, clippy::result_unit_err)]
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, ()> {
+        let length = self.encoded_len();
+        if out.len() < length {
+            return Err(());
+        }
+        if let Self::FireBurst { count } = self && *count > MAX_BURST_COUNT {
+            return Err(());
+        }
+        let mut buffer = SliceWriter::new(out);
+        match self {
+            Self::SetDebugLed(state) => {
+                buffer.push(CONTROLLER_MESSAGE_ID_SET_DEBUG_LED | MESSAGE_START_BIT);
+                buffer.push(if *state { 1 } else { 0 });
             }
-            true
-        } else {
-            false
+            Self::GetParam(param) => {
+                buffer.push(CONTROLLER_MESSAGE_ID_GET_PARAM | MESSAGE_START_BIT);
+                buffer.push((*param).into());
+            },
+            Self::SetParam(parameter_value) => {
+                let (param, value) = (*parameter_value).into();
+                buffer.push(CONTROLLER_MESSAGE_ID_SET_PARAM | MESSAGE_START_BIT);
+                buffer.push(param.into());
+                buffer.push(((value >> 0) & 0x7F) as u8);
+                buffer.push(((value >> 7) & 0x7F) as u8);
+            },
+            Self::GetStat(stat) => {
+                buffer.push(CONTROLLER_MESSAGE_ID_GET_STAT | MESSAGE_START_BIT);
+                buffer.push((*stat).into());
+            },
+            Self::ResetStats => {
+                buffer.push(CONTROLLER_MESSAGE_ID_RESET_STATS | MESSAGE_START_BIT);
+            },
+            Self::KeepAlive => {
+                buffer.push(CONTROLLER_MESSAGE_ID_KEEP_ALIVE | MESSAGE_START_BIT);
+            },
+            Self::Run => {
+                buffer.push(CONTROLLER_MESSAGE_ID_RUN | MESSAGE_START_BIT);
+            },
+            Self::Stop => {
+                buffer.push(CONTROLLER_MESSAGE_ID_STOP | MESSAGE_START_BIT);
+            },
+            Self::EmergencyStop => {
+                buffer.push(CONTROLLER_MESSAGE_ID_EMERGENCY_STOP | MESSAGE_START_BIT);
+                for check_byte in EMERGENCY_STOP_CHECK_BYTES {
+                    buffer.push(check_byte);
+                }
+            },
+            Self::BeginTransaction => {
+                buffer.push(CONTROLLER_MESSAGE_ID_BEGIN_TRANSACTION | MESSAGE_START_BIT);
+            },
+            Self::Commit => {
+                buffer.push(CONTROLLER_MESSAGE_ID_COMMIT | MESSAGE_START_BIT);
+            },
+            Self::Abort => {
+                buffer.push(CONTROLLER_MESSAGE_ID_ABORT | MESSAGE_START_BIT);
+            },
+            Self::GetLimit(param) => {
+                buffer.push(CONTROLLER_MESSAGE_ID_GET_LIMIT | MESSAGE_START_BIT);
+                buffer.push((*param).into());
+            },
+            Self::SetLimit(limit) => {
+                let (param, min) = limit.min.into();
+                let (_, max) = limit.max.into();
+                buffer.push(CONTROLLER_MESSAGE_ID_SET_LIMIT | MESSAGE_START_BIT);
+                buffer.push(param.into());
+                buffer.push(((min >> 0) & 0x7F) as u8);
+                buffer.push(((min >> 7) & 0x7F) as u8);
+                buffer.push(((max >> 0) & 0x7F) as u8);
+                buffer.push(((max >> 7) & 0x7F) as u8);
+            },
+            Self::EngineeringUnlock { pin } => {
+                buffer.push(CONTROLLER_MESSAGE_ID_ENGINEERING_UNLOCK | MESSAGE_START_BIT);
+                buffer.push(((*pin >>  0) & 0x7F) as u8);
+                buffer.push(((*pin >>  7) & 0x7F) as u8);
+                buffer.push(((*pin >> 14) & 0x7F) as u8);
+                buffer.push(((*pin >> 21) & 0x7F) as u8);
+                buffer.push(((*pin >> 28) & 0x7F) as u8);
+            },
+            Self::EngineeringLock => {
+                buffer.push(CONTROLLER_MESSAGE_ID_ENGINEERING_LOCK | MESSAGE_START_BIT);
+            },
+            Self::FireSingle => {
+                buffer.push(CONTROLLER_MESSAGE_ID_FIRE_SINGLE | MESSAGE_START_BIT);
+            },
+            Self::FireBurst { count } => {
+                buffer.push(CONTROLLER_MESSAGE_ID_FIRE_BURST | MESSAGE_START_BIT);
+                buffer.push(((*count >> 0) & 0x7F) as u8);
+                buffer.push(((*count >> 7) & 0x7F) as u8);
+            },
+            Self::RunFor { duration_ms } => {
+                buffer.push(CONTROLLER_MESSAGE_ID_RUN_FOR | MESSAGE_START_BIT);
+                buffer.push(((*duration_ms >>  0) & 0x7F) as u8);
+                buffer.push(((*duration_ms >>  7) & 0x7F) as u8);
+                buffer.push(((*duration_ms >> 14) & 0x7F) as u8);
+                buffer.push(((*duration_ms >> 21) & 0x7F) as u8);
+                buffer.push(((*duration_ms >> 28) & 0x7F) as u8);
+            },
+            Self::NoteOn { note, velocity } => {
+                buffer.push(CONTROLLER_MESSAGE_ID_NOTE_ON | MESSAGE_START_BIT);
+                buffer.push(note & 0x7F);
+                buffer.push(velocity & 0x7F);
+            },
+            Self::NoteOff { note } => {
+                buffer.push(CONTROLLER_MESSAGE_ID_NOTE_OFF | MESSAGE_START_BIT);
+                buffer.push(note & 0x7F);
+            },
+            Self::AllNotesOff => {
+                buffer.push(CONTROLLER_MESSAGE_ID_ALL_NOTES_OFF | MESSAGE_START_BIT);
+            },
+            Self::BeginRampUpload { point_count } => {
+                buffer.push(CONTROLLER_MESSAGE_ID_BEGIN_RAMP_UPLOAD | MESSAGE_START_BIT);
+                buffer.push(point_count & 0x7F);
+            },
+            Self::UploadRampPoint { index, point } => {
+                let power = ((point.power * 16384.0) as i32).clamp(0, 0x3FFF) as u16;
+                buffer.push(CONTROLLER_MESSAGE_ID_UPLOAD_RAMP_POINT | MESSAGE_START_BIT);
+                buffer.push(index & 0x7F);
+                buffer.push(((point.time_us >>  0) & 0x7F) as u8);
+                buffer.push(((point.time_us >>  7) & 0x7F) as u8);
+                buffer.push(((point.time_us >> 14) & 0x7F) as u8);
+                buffer.push(((power >> 0) & 0x7F) as u8);
+                buffer.push(((power >> 7) & 0x7F) as u8);
+            },
+            Self::CommitRampUpload => {
+                buffer.push(CONTROLLER_MESSAGE_ID_COMMIT_RAMP_UPLOAD | MESSAGE_START_BIT);
+            },
+            Self::ClockSyncRequest { controller_time: time } | Self::RunAt { timestamp: time } => {
+                let id = match self {
+                    Self::RunAt { .. } => CONTROLLER_MESSAGE_ID_RUN_AT,
+                    _ => CONTROLLER_MESSAGE_ID_CLOCK_SYNC_REQUEST,
+                };
+                buffer.push(id | MESSAGE_START_BIT);
+                buffer.push(((*time >>  0) & 0x7F) as u8);
+                buffer.push(((*time >>  7) & 0x7F) as u8);
+                buffer.push(((*time >> 14) & 0x7F) as u8);
+                buffer.push(((*time >> 21) & 0x7F) as u8);
+                buffer.push(((*time >> 28) & 0x7F) as u8);
+            },
+            Self::Ping(seq) => {
+                buffer.push(CONTROLLER_MESSAGE_ID_PING | MESSAGE_START_BIT);
+                buffer.push(((*seq >>  0) & 0x7F) as u8);
+                buffer.push(((*seq >>  7) & 0x7F) as u8);
+                buffer.push(((*seq >> 14) & 0x7F) as u8);
+                buffer.push(((*seq >> 21) & 0x7F) as u8);
+            },
+        }
+        Ok(length)
+    }
+
+    pub fn try_send(&self, buffer: &mut impl ByteSink) -> bool {
+        let mut frame = [0u8; MAX_FRAME_LENGTH];
+        match self.encode(&mut frame) {
+            Ok(length) => push_frame(&frame[..length], buffer),
+            Err(()) => false,
+        }
+    }
+
+    /// Decodes the first frame in `input`, skipping anything before its start byte. Returns the
+    /// message and how many bytes of `input` were used up.
+    pub fn decode(input: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let mut reader = SliceReader::new(input);
+        match Self::try_receive_or_reject(&mut reader) {
+            Ok(Some(message)) => Ok((message, reader.position())),
+            Ok(None) => Err(DecodeError::Incomplete { skipped: reader.position() }),
+            Err(_) => Err(DecodeError::Invalid { consumed: reader.position() }),
         }
     }
     
-    pub fn try_receive<const N: usize>(rx_buffer: &mut SerialBuffer<N>) -> Result<Option<Self>, ()> {
+    #[allow(clippy::result_unit_err)]
+    pub fn try_receive(rx_buffer: &mut impl ByteSource) -> Result<Option<Self>, ()> {
+        Self::try_receive_or_reject(rx_buffer).map_err(|_| ())
+    }
+
+    // Same as try_receive, but says which message failed to decode and why.
+    #[allow(clippy::identity_op)]
+    pub(crate) fn try_receive_or_reject(rx_buffer: &mut impl ByteSource) -> Result<Option<Self>, (u8, RejectReason)> {
         while let Some(id_byte) = rx_buffer.peek() {
             if (id_byte & MESSAGE_START_BIT) != 0 {
                 break;
             }
+            match take_unmarked_emergency_stop(rx_buffer) {
+                Some(true) => return Ok(Some(ControllerMessage::EmergencyStop)),
+                Some(false) => {},
+                None => return Ok(None),
+            }
             rx_buffer.pop();
         }
         if let Some(id) = rx_buffer.peek() {
             let id = id & !MESSAGE_START_BIT;
-            let length = match id {
-                CONTROLLER_MESSAGE_ID_SET_DEBUG_LED => 2,
-                CONTROLLER_MESSAGE_ID_GET_PARAM => 2,
-                CONTROLLER_MESSAGE_ID_SET_PARAM => 4,
-                CONTROLLER_MESSAGE_ID_GET_STAT => 2,
-                CONTROLLER_MESSAGE_ID_RESET_STATS => 1,
-                CONTROLLER_MESSAGE_ID_RUN => 1,
-                CONTROLLER_MESSAGE_ID_STOP => 1,
-                CONTROLLER_MESSAGE_ID_KEEP_ALIVE => 1,
-                CONTROLLER_MESSAGE_ID_PING => 5,
-                _ => {
+            let id = if is_emergency_stop_id(id) { CONTROLLER_MESSAGE_ID_EMERGENCY_STOP } else { id };
+            let length = match controller_message_length(id) {
+                Some(length) => length,
+                None => {
                     rx_buffer.pop();
-                    Err(())?
+                    Err((id, RejectReason::UnknownMessage))?
                 }
             };
+            if id != CONTROLLER_MESSAGE_ID_EMERGENCY_STOP && drop_interrupted_frame(rx_buffer, length) {
+                return Err((id, RejectReason::Malformed));
+            }
             if rx_buffer.count() >= length {
                 rx_buffer.pop();
                 match id {
@@ -364,20 +926,22 @@ impl ControllerMessage {
                     },
                     CONTROLLER_MESSAGE_ID_GET_PARAM => {
                         let param_id = rx_buffer.pop().unwrap();
-                        return Ok(Some(ControllerMessage::GetParam(Parameter::try_from(param_id)?)));
+                        let param = Parameter::try_from(param_id).map_err(|_| (id, RejectReason::UnknownParameter))?;
+                        return Ok(Some(ControllerMessage::GetParam(param)));
                     },
                     CONTROLLER_MESSAGE_ID_SET_PARAM => {
                         let param_id = rx_buffer.pop().unwrap();
                         let value = 
                             ((rx_buffer.pop().unwrap() as u16) << 0) |
                             ((rx_buffer.pop().unwrap() as u16) << 7);
-                        let param = Parameter::try_from(param_id)?;
-                        let param_value = ParameterValue::try_from((param, value))?;
+                        let param = Parameter::try_from(param_id).map_err(|_| (id, RejectReason::UnknownParameter))?;
+                        let param_value = ParameterValue::try_from((param, value)).map_err(|_| (id, RejectReason::OutOfRange))?;
                         return Ok(Some(ControllerMessage::SetParam(param_value)));
                     },
                     CONTROLLER_MESSAGE_ID_GET_STAT => {
                         let param_id = rx_buffer.pop().unwrap();
-                        return Ok(Some(ControllerMessage::GetStat(Statistic::try_from(param_id)?)));
+                        let stat = Statistic::try_from(param_id).map_err(|_| (id, RejectReason::UnknownStatistic))?;
+                        return Ok(Some(ControllerMessage::GetStat(stat)));
                     },
                     CONTROLLER_MESSAGE_ID_RESET_STATS => {
                         return Ok(Some(ControllerMessage::ResetStats));
@@ -391,6 +955,121 @@ impl ControllerMessage {
                     CONTROLLER_MESSAGE_ID_STOP => {
                         return Ok(Some(ControllerMessage::Stop));
                     },
+                    CONTROLLER_MESSAGE_ID_EMERGENCY_STOP => {
+                        // Tolerate a single corrupted check byte
+                        let mut matching = 0;
+                        for check_byte in EMERGENCY_STOP_CHECK_BYTES {
+                            if rx_buffer.pop().map(|byte| byte & 0x7F) == Some(check_byte) {
+                                matching += 1;
+                            }
+                        }
+                        if matching >= EMERGENCY_STOP_CHECK_BYTES.len() - 1 {
+                            return Ok(Some(ControllerMessage::EmergencyStop));
+                        } else {
+                            return Err((id, RejectReason::Malformed));
+                        }
+                    },
+                    CONTROLLER_MESSAGE_ID_BEGIN_TRANSACTION => {
+                        return Ok(Some(ControllerMessage::BeginTransaction));
+                    },
+                    CONTROLLER_MESSAGE_ID_COMMIT => {
+                        return Ok(Some(ControllerMessage::Commit));
+                    },
+                    CONTROLLER_MESSAGE_ID_ABORT => {
+                        return Ok(Some(ControllerMessage::Abort));
+                    },
+                    CONTROLLER_MESSAGE_ID_GET_LIMIT => {
+                        let param_id = rx_buffer.pop().unwrap();
+                        let param = Parameter::try_from(param_id).map_err(|_| (id, RejectReason::UnknownParameter))?;
+                        return Ok(Some(ControllerMessage::GetLimit(param)));
+                    },
+                    CONTROLLER_MESSAGE_ID_SET_LIMIT => {
+                        let param_id = rx_buffer.pop().unwrap();
+                        let min =
+                            ((rx_buffer.pop().unwrap() as u16) << 0) |
+                            ((rx_buffer.pop().unwrap() as u16) << 7);
+                        let max =
+                            ((rx_buffer.pop().unwrap() as u16) << 0) |
+                            ((rx_buffer.pop().unwrap() as u16) << 7);
+                        let param = Parameter::try_from(param_id).map_err(|_| (id, RejectReason::UnknownParameter))?;
+                        let min = ParameterValue::try_from((param, min)).map_err(|_| (id, RejectReason::OutOfRange))?;
+                        let max = ParameterValue::try_from((param, max)).map_err(|_| (id, RejectReason::OutOfRange))?;
+                        return Ok(Some(ControllerMessage::SetLimit(ParameterLimit { min, max })));
+                    },
+                    CONTROLLER_MESSAGE_ID_ENGINEERING_UNLOCK => {
+                        let pin =
+                            ((rx_buffer.pop().unwrap() as u32) <<  0) |
+                            ((rx_buffer.pop().unwrap() as u32) <<  7) |
+                            ((rx_buffer.pop().unwrap() as u32) << 14) |
+                            ((rx_buffer.pop().unwrap() as u32) << 21) |
+                            ((rx_buffer.pop().unwrap() as u32) << 28);
+                        return Ok(Some(ControllerMessage::EngineeringUnlock { pin }));
+                    },
+                    CONTROLLER_MESSAGE_ID_ENGINEERING_LOCK => {
+                        return Ok(Some(ControllerMessage::EngineeringLock));
+                    },
+                    CONTROLLER_MESSAGE_ID_FIRE_SINGLE => {
+                        return Ok(Some(ControllerMessage::FireSingle));
+                    },
+                    CONTROLLER_MESSAGE_ID_FIRE_BURST => {
+                        let count =
+                            ((rx_buffer.pop().unwrap() as u16) << 0) |
+                            ((rx_buffer.pop().unwrap() as u16) << 7);
+                        return Ok(Some(ControllerMessage::FireBurst { count }));
+                    },
+                    CONTROLLER_MESSAGE_ID_RUN_FOR => {
+                        let duration_ms =
+                            ((rx_buffer.pop().unwrap() as u32) <<  0) |
+                            ((rx_buffer.pop().unwrap() as u32) <<  7) |
+                            ((rx_buffer.pop().unwrap() as u32) << 14) |
+                            ((rx_buffer.pop().unwrap() as u32) << 21) |
+                            ((rx_buffer.pop().unwrap() as u32) << 28);
+                        return Ok(Some(ControllerMessage::RunFor { duration_ms }));
+                    },
+                    CONTROLLER_MESSAGE_ID_NOTE_ON => {
+                        let note = rx_buffer.pop().unwrap();
+                        let velocity = rx_buffer.pop().unwrap();
+                        return Ok(Some(ControllerMessage::NoteOn { note, velocity }));
+                    },
+                    CONTROLLER_MESSAGE_ID_NOTE_OFF => {
+                        let note = rx_buffer.pop().unwrap();
+                        return Ok(Some(ControllerMessage::NoteOff { note }));
+                    },
+                    CONTROLLER_MESSAGE_ID_ALL_NOTES_OFF => {
+                        return Ok(Some(ControllerMessage::AllNotesOff));
+                    },
+                    CONTROLLER_MESSAGE_ID_BEGIN_RAMP_UPLOAD => {
+                        let point_count = rx_buffer.pop().unwrap();
+                        return Ok(Some(ControllerMessage::BeginRampUpload { point_count }));
+                    },
+                    CONTROLLER_MESSAGE_ID_UPLOAD_RAMP_POINT => {
+                        let index = rx_buffer.pop().unwrap();
+                        let time_us =
+                            ((rx_buffer.pop().unwrap() as u16) <<  0) |
+                            ((rx_buffer.pop().unwrap() as u16) <<  7) |
+                            ((rx_buffer.pop().unwrap() as u16) << 14);
+                        let power =
+                            ((rx_buffer.pop().unwrap() as u16) << 0) |
+                            ((rx_buffer.pop().unwrap() as u16) << 7);
+                        let point = RampPoint { time_us, power: power as f32 / 16383.0 };
+                        return Ok(Some(ControllerMessage::UploadRampPoint { index, point }));
+                    },
+                    CONTROLLER_MESSAGE_ID_COMMIT_RAMP_UPLOAD => {
+                        return Ok(Some(ControllerMessage::CommitRampUpload));
+                    },
+                    CONTROLLER_MESSAGE_ID_CLOCK_SYNC_REQUEST | CONTROLLER_MESSAGE_ID_RUN_AT => {
+                        let time =
+                            ((rx_buffer.pop().unwrap() as u32) <<  0) |
+                            ((rx_buffer.pop().unwrap() as u32) <<  7) |
+                            ((rx_buffer.pop().unwrap() as u32) << 14) |
+                            ((rx_buffer.pop().unwrap() as u32) << 21) |
+                            ((rx_buffer.pop().unwrap() as u32) << 28);
+                        if id == CONTROLLER_MESSAGE_ID_RUN_AT {
+                            return Ok(Some(ControllerMessage::RunAt { timestamp: time }));
+                        } else {
+                            return Ok(Some(ControllerMessage::ClockSyncRequest { controller_time: time }));
+                        }
+                    },
                     CONTROLLER_MESSAGE_ID_PING => {
                         let seq = 
                             (rx_buffer.pop().unwrap() as u32) << 0  |
@@ -414,17 +1093,100 @@ pub enum RemoteMessage {
     Ping(u32),
     LockFailed,
     OcdTripped,
+    Status(RemoteStatus),
+    EmergencyStopAck,
+    CommandRejected { message_id: u8, reason: RejectReason },
+    ParamApplied(ParameterValue),
+    TransactionCommitted,
+    LimitResult(ParameterLimit),
+    EngineeringUnlocked,
+    EngineeringLocked,
+    ClockSyncResponse { controller_time: u32, receive_time: u32, send_time: u32 },
+    RunComplete { bangs: u32 },
+    RampProfileCommitted { point_count: u8 },
 }
 
 const REMOTE_MESSAGE_ID_GET_PARAM_RESULT: u8 = 0;
 const REMOTE_MESSAGE_ID_GET_STAT_RESULT: u8 = 1;
 const REMOTE_MESSAGE_ID_LOCK_FAILED: u8 = 2;
 const REMOTE_MESSAGE_ID_OCD_TRIPPED: u8 = 3;
+const REMOTE_MESSAGE_ID_STATUS: u8 = 4;
+const REMOTE_MESSAGE_ID_EMERGENCY_STOP_ACK: u8 = 5;
+const REMOTE_MESSAGE_ID_COMMAND_REJECTED: u8 = 6;
+const REMOTE_MESSAGE_ID_PARAM_APPLIED: u8 = 7;
+const REMOTE_MESSAGE_ID_TRANSACTION_COMMITTED: u8 = 8;
+const REMOTE_MESSAGE_ID_LIMIT_RESULT: u8 = 9;
+const REMOTE_MESSAGE_ID_ENGINEERING_UNLOCKED: u8 = 10;
+const REMOTE_MESSAGE_ID_ENGINEERING_LOCKED: u8 = 11;
+const REMOTE_MESSAGE_ID_CLOCK_SYNC_RESPONSE: u8 = 12;
+const REMOTE_MESSAGE_ID_RUN_COMPLETE: u8 = 13;
+const REMOTE_MESSAGE_ID_RAMP_PROFILE_COMMITTED: u8 = 14;
 const REMOTE_MESSAGE_ID_PING: u8 = 0x7F;
+// 0x70 is taken by the address header, see `AddressFilter`
+
+const fn remote_message_length(id: u8) -> Option<usize> {
+    Some(match id {
+        REMOTE_MESSAGE_ID_GET_PARAM_RESULT => 4,
+        REMOTE_MESSAGE_ID_GET_STAT_RESULT  => 4,
+        REMOTE_MESSAGE_ID_PING             => 5,
+        REMOTE_MESSAGE_ID_LOCK_FAILED      => 1,
+        REMOTE_MESSAGE_ID_OCD_TRIPPED      => 1,
+        REMOTE_MESSAGE_ID_STATUS           => 10,
+        REMOTE_MESSAGE_ID_EMERGENCY_STOP_ACK => 1,
+        REMOTE_MESSAGE_ID_COMMAND_REJECTED => 3,
+        REMOTE_MESSAGE_ID_PARAM_APPLIED    => 4,
+        REMOTE_MESSAGE_ID_TRANSACTION_COMMITTED => 1,
+        REMOTE_MESSAGE_ID_LIMIT_RESULT     => 6,
+        REMOTE_MESSAGE_ID_ENGINEERING_UNLOCKED => 1,
+        REMOTE_MESSAGE_ID_ENGINEERING_LOCKED => 1,
+        REMOTE_MESSAGE_ID_CLOCK_SYNC_RESPONSE => 16,
+        REMOTE_MESSAGE_ID_RUN_COMPLETE     => 6,
+        REMOTE_MESSAGE_ID_RAMP_PROFILE_COMMITTED => 2,
+        _ => return None,
+    })
+}
 
 impl RemoteMessage {
-    pub fn try_send<const N: usize>(&self, tx_buffer: &mut SerialBuffer<N>) -> bool {
+    pub const fn message_id(&self) -> u8 {
         match self {
+            Self::GetParamResult(..)         => REMOTE_MESSAGE_ID_GET_PARAM_RESULT,
+            Self::GetStatResult(..)          => REMOTE_MESSAGE_ID_GET_STAT_RESULT,
+            Self::Ping(..)                   => REMOTE_MESSAGE_ID_PING,
+            Self::LockFailed                 => REMOTE_MESSAGE_ID_LOCK_FAILED,
+            Self::OcdTripped                 => REMOTE_MESSAGE_ID_OCD_TRIPPED,
+            Self::Status(..)                 => REMOTE_MESSAGE_ID_STATUS,
+            Self::EmergencyStopAck           => REMOTE_MESSAGE_ID_EMERGENCY_STOP_ACK,
+            Self::CommandRejected { .. }     => REMOTE_MESSAGE_ID_COMMAND_REJECTED,
+            Self::ParamApplied(..)           => REMOTE_MESSAGE_ID_PARAM_APPLIED,
+            Self::TransactionCommitted       => REMOTE_MESSAGE_ID_TRANSACTION_COMMITTED,
+            Self::LimitResult(..)            => REMOTE_MESSAGE_ID_LIMIT_RESULT,
+            Self::EngineeringUnlocked        => REMOTE_MESSAGE_ID_ENGINEERING_UNLOCKED,
+            Self::EngineeringLocked          => REMOTE_MESSAGE_ID_ENGINEERING_LOCKED,
+            Self::ClockSyncResponse { .. }   => REMOTE_MESSAGE_ID_CLOCK_SYNC_RESPONSE,
+            Self::RunComplete { .. }         => REMOTE_MESSAGE_ID_RUN_COMPLETE,
+            Self::RampProfileCommitted { .. } => REMOTE_MESSAGE_ID_RAMP_PROFILE_COMMITTED,
+        }
+    }
+
+    /// Length of the encoded frame, start byte included
+    pub const fn encoded_len(&self) -> usize {
+        match Self::encoded_len_of(self.message_id()) {
+            Some(length) => length,
+            None => 0,
+        }
+    }
+
+    /// Length of the frame for `message_id`, start byte included, or `None` for an unknown id
+    pub const fn encoded_len_of(message_id: u8) -> Option<usize> {
+        remote_message_length(message_id)
+    }
+
+    /// Encodes the frame into the start of `out` and returns its length. Fails if `out` is too
+    /// short for it, or for a `Status` with a preset over 127.
+    #[allow(clippy::identity_op, clippy::result_unit_err)]
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, ()> {
+        let mut tx_buffer = SliceWriter::new(out);
+        let written = match self {
             Self::Ping(seq) => {
                 if tx_buffer.free_space() >= 5 {
                     tx_buffer.push(REMOTE_MESSAGE_ID_PING | MESSAGE_START_BIT);
@@ -437,9 +1199,13 @@ impl RemoteMessage {
                     false
                 }
             },
-            Self::GetParamResult(param_value) => {
+            Self::GetParamResult(param_value) | Self::ParamApplied(param_value) => {
                 if tx_buffer.free_space() >= 4 {
-                    tx_buffer.push(REMOTE_MESSAGE_ID_GET_PARAM_RESULT | MESSAGE_START_BIT);
+                    let id = match self {
+                        Self::ParamApplied(..) => REMOTE_MESSAGE_ID_PARAM_APPLIED,
+                        _ => REMOTE_MESSAGE_ID_GET_PARAM_RESULT,
+                    };
+                    tx_buffer.push(id | MESSAGE_START_BIT);
                     let (param, value) = (*param_value).into();
                     tx_buffer.push((param).into());
                     tx_buffer.push(((value >>  0) & 0x7F) as u8);
@@ -477,10 +1243,147 @@ impl RemoteMessage {
                     false
                 }
             },
+            Self::Status(status) => {
+                if tx_buffer.free_space() >= 10 && status.preset <= 0x7F {
+                    let run_mode: u16 = status.run_mode.into();
+                    tx_buffer.push(REMOTE_MESSAGE_ID_STATUS | MESSAGE_START_BIT);
+                    tx_buffer.push(status.run_state.into());
+                    tx_buffer.push(run_mode as u8);
+                    tx_buffer.push(status.preset);
+                    tx_buffer.push(status.faults.0 & 0x7F);
+                    tx_buffer.push(((status.bang_count >>  0) & 0x7F) as u8);
+                    tx_buffer.push(((status.bang_count >>  7) & 0x7F) as u8);
+                    tx_buffer.push(((status.bang_count >> 14) & 0x7F) as u8);
+                    tx_buffer.push(((status.bang_count >> 21) & 0x7F) as u8);
+                    tx_buffer.push(((status.bang_count >> 28) & 0x7F) as u8);
+                    true
+                } else {
+                    false
+                }
+            },
+            Self::EmergencyStopAck => {
+                if tx_buffer.free_space() >= 1 {
+                    tx_buffer.push(REMOTE_MESSAGE_ID_EMERGENCY_STOP_ACK | MESSAGE_START_BIT);
+                    true
+                } else {
+                    false
+                }
+            },
+            Self::TransactionCommitted => {
+                if tx_buffer.free_space() >= 1 {
+                    tx_buffer.push(REMOTE_MESSAGE_ID_TRANSACTION_COMMITTED | MESSAGE_START_BIT);
+                    true
+                } else {
+                    false
+                }
+            },
+            Self::LimitResult(limit) => {
+                if tx_buffer.free_space() >= 6 {
+                    let (param, min) = limit.min.into();
+                    let (_, max) = limit.max.into();
+                    tx_buffer.push(REMOTE_MESSAGE_ID_LIMIT_RESULT | MESSAGE_START_BIT);
+                    tx_buffer.push(param.into());
+                    tx_buffer.push(((min >>  0) & 0x7F) as u8);
+                    tx_buffer.push(((min >>  7) & 0x7F) as u8);
+                    tx_buffer.push(((max >>  0) & 0x7F) as u8);
+                    tx_buffer.push(((max >>  7) & 0x7F) as u8);
+                    true
+                } else {
+                    false
+                }
+            },
+            Self::EngineeringUnlocked => {
+                if tx_buffer.free_space() >= 1 {
+                    tx_buffer.push(REMOTE_MESSAGE_ID_ENGINEERING_UNLOCKED | MESSAGE_START_BIT);
+                    true
+                } else {
+                    false
+                }
+            },
+            Self::EngineeringLocked => {
+                if tx_buffer.free_space() >= 1 {
+                    tx_buffer.push(REMOTE_MESSAGE_ID_ENGINEERING_LOCKED | MESSAGE_START_BIT);
+                    true
+                } else {
+                    false
+                }
+            },
+            Self::ClockSyncResponse { controller_time, receive_time, send_time } => {
+                if tx_buffer.free_space() >= 16 {
+                    tx_buffer.push(REMOTE_MESSAGE_ID_CLOCK_SYNC_RESPONSE | MESSAGE_START_BIT);
+                    for time in [controller_time, receive_time, send_time] {
+                        tx_buffer.push(((time >>  0) & 0x7F) as u8);
+                        tx_buffer.push(((time >>  7) & 0x7F) as u8);
+                        tx_buffer.push(((time >> 14) & 0x7F) as u8);
+                        tx_buffer.push(((time >> 21) & 0x7F) as u8);
+                        tx_buffer.push(((time >> 28) & 0x7F) as u8);
+                    }
+                    true
+                } else {
+                    false
+                }
+            },
+            Self::RunComplete { bangs } => {
+                if tx_buffer.free_space() >= 6 {
+                    tx_buffer.push(REMOTE_MESSAGE_ID_RUN_COMPLETE | MESSAGE_START_BIT);
+                    tx_buffer.push(((bangs >>  0) & 0x7F) as u8);
+                    tx_buffer.push(((bangs >>  7) & 0x7F) as u8);
+                    tx_buffer.push(((bangs >> 14) & 0x7F) as u8);
+                    tx_buffer.push(((bangs >> 21) & 0x7F) as u8);
+                    tx_buffer.push(((bangs >> 28) & 0x7F) as u8);
+                    true
+                } else {
+                    false
+                }
+            },
+            Self::RampProfileCommitted { point_count } => {
+                if tx_buffer.free_space() >= 2 {
+                    tx_buffer.push(REMOTE_MESSAGE_ID_RAMP_PROFILE_COMMITTED | MESSAGE_START_BIT);
+                    tx_buffer.push(point_count & 0x7F);
+                    true
+                } else {
+                    false
+                }
+            },
+            Self::CommandRejected { message_id, reason } => {
+                if tx_buffer.free_space() >= 3 {
+                    tx_buffer.push(REMOTE_MESSAGE_ID_COMMAND_REJECTED | MESSAGE_START_BIT);
+                    tx_buffer.push(message_id & 0x7F);
+                    tx_buffer.push((*reason).into());
+                    true
+                } else {
+                    false
+                }
+            },
+        };
+        if written {
+            Ok(tx_buffer.position())
+        } else {
+            Err(())
         }
     }
 
-    pub fn try_receive<const N: usize>(rx_buffer: &mut SerialBuffer<N>) -> Result<Option<Self>, ()> {
+    pub fn try_send(&self, tx_buffer: &mut impl ByteSink) -> bool {
+        let mut frame = [0u8; MAX_FRAME_LENGTH];
+        match self.encode(&mut frame) {
+            Ok(length) => push_frame(&frame[..length], tx_buffer),
+            Err(()) => false,
+        }
+    }
+
+    /// Decodes the first frame in `input`, skipping anything before its start byte. Returns the
+    /// message and how many bytes of `input` were used up.
+    pub fn decode(input: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let mut reader = SliceReader::new(input);
+        match Self::try_receive(&mut reader) {
+            Ok(Some(message)) => Ok((message, reader.position())),
+            Ok(None) => Err(DecodeError::Incomplete { skipped: reader.position() }),
+            Err(()) => Err(DecodeError::Invalid { consumed: reader.position() }),
+        }
+    }
+
+    #[allow(clippy::identity_op, clippy::result_unit_err)]
+    pub fn try_receive(rx_buffer: &mut impl ByteSource) -> Result<Option<Self>, ()> {
         while let Some(id_byte) = rx_buffer.peek() {
             if (id_byte & MESSAGE_START_BIT) != 0 {
                 break;
@@ -489,25 +1392,28 @@ impl RemoteMessage {
         }
         if let Some(id) = rx_buffer.peek() {
             let id = id & !MESSAGE_START_BIT;
-            let length = match id  {
-                REMOTE_MESSAGE_ID_GET_PARAM_RESULT => 4,
-                REMOTE_MESSAGE_ID_GET_STAT_RESULT  => 4,
-                REMOTE_MESSAGE_ID_PING             => 5,
-                REMOTE_MESSAGE_ID_LOCK_FAILED      => 1,
-                REMOTE_MESSAGE_ID_OCD_TRIPPED      => 1,
-                _ => { rx_buffer.pop(); return Err(()) }
+            let length = match remote_message_length(id) {
+                Some(length) => length,
+                None => { rx_buffer.pop(); return Err(()) }
             };
+            if drop_interrupted_frame(rx_buffer, length) {
+                return Err(());
+            }
             if rx_buffer.count() >= length {
                 _ = rx_buffer.pop();
                 match id {
-                    REMOTE_MESSAGE_ID_GET_PARAM_RESULT => {
+                    REMOTE_MESSAGE_ID_GET_PARAM_RESULT | REMOTE_MESSAGE_ID_PARAM_APPLIED => {
                         let param_id = rx_buffer.pop().unwrap();
                         let value = 
                             ((rx_buffer.pop().unwrap() as u16) << 0) |
                             ((rx_buffer.pop().unwrap() as u16) << 7);
                         let param = Parameter::try_from(param_id)?;
                         let param_value = ParameterValue::try_from((param, value))?;
-                        return Ok(Some(RemoteMessage::GetParamResult(param_value)));
+                        if id == REMOTE_MESSAGE_ID_PARAM_APPLIED {
+                            Ok(Some(Self::ParamApplied(param_value)))
+                        } else {
+                            Ok(Some(Self::GetParamResult(param_value)))
+                        }
                     },
                     REMOTE_MESSAGE_ID_GET_STAT_RESULT => {
                         let stat = Statistic::try_from(rx_buffer.pop().unwrap())?;
@@ -530,7 +1436,82 @@ impl RemoteMessage {
                     },
                     REMOTE_MESSAGE_ID_OCD_TRIPPED => {
                         Ok(Some(Self::OcdTripped))
-                    }
+                    },
+                    REMOTE_MESSAGE_ID_STATUS => {
+                        let run_state = RunState::try_from(rx_buffer.pop().unwrap());
+                        let run_mode = RunMode::try_from(rx_buffer.pop().unwrap() as u16);
+                        let preset = rx_buffer.pop().unwrap();
+                        let faults = FaultFlags(rx_buffer.pop().unwrap());
+                        let bang_count =
+                            ((rx_buffer.pop().unwrap() as u32) <<  0) |
+                            ((rx_buffer.pop().unwrap() as u32) <<  7) |
+                            ((rx_buffer.pop().unwrap() as u32) << 14) |
+                            ((rx_buffer.pop().unwrap() as u32) << 21) |
+                            ((rx_buffer.pop().unwrap() as u32) << 28);
+                        Ok(Some(Self::Status(RemoteStatus {
+                            run_state: run_state?,
+                            run_mode: run_mode?,
+                            preset,
+                            faults,
+                            bang_count,
+                        })))
+                    },
+                    REMOTE_MESSAGE_ID_EMERGENCY_STOP_ACK => {
+                        Ok(Some(Self::EmergencyStopAck))
+                    },
+                    REMOTE_MESSAGE_ID_TRANSACTION_COMMITTED => {
+                        Ok(Some(Self::TransactionCommitted))
+                    },
+                    REMOTE_MESSAGE_ID_LIMIT_RESULT => {
+                        let param_id = rx_buffer.pop().unwrap();
+                        let min =
+                            ((rx_buffer.pop().unwrap() as u16) <<  0) |
+                            ((rx_buffer.pop().unwrap() as u16) <<  7);
+                        let max =
+                            ((rx_buffer.pop().unwrap() as u16) <<  0) |
+                            ((rx_buffer.pop().unwrap() as u16) <<  7);
+                        let param = Parameter::try_from(param_id)?;
+                        let min = ParameterValue::try_from((param, min))?;
+                        let max = ParameterValue::try_from((param, max))?;
+                        Ok(Some(Self::LimitResult(ParameterLimit { min, max })))
+                    },
+                    REMOTE_MESSAGE_ID_ENGINEERING_UNLOCKED => {
+                        Ok(Some(Self::EngineeringUnlocked))
+                    },
+                    REMOTE_MESSAGE_ID_ENGINEERING_LOCKED => {
+                        Ok(Some(Self::EngineeringLocked))
+                    },
+                    REMOTE_MESSAGE_ID_CLOCK_SYNC_RESPONSE => {
+                        let mut times = [0u32; 3];
+                        for time in &mut times {
+                            *time =
+                                ((rx_buffer.pop().unwrap() as u32) <<  0) |
+                                ((rx_buffer.pop().unwrap() as u32) <<  7) |
+                                ((rx_buffer.pop().unwrap() as u32) << 14) |
+                                ((rx_buffer.pop().unwrap() as u32) << 21) |
+                                ((rx_buffer.pop().unwrap() as u32) << 28);
+                        }
+                        let [controller_time, receive_time, send_time] = times;
+                        Ok(Some(Self::ClockSyncResponse { controller_time, receive_time, send_time }))
+                    },
+                    REMOTE_MESSAGE_ID_RUN_COMPLETE => {
+                        let bangs =
+                            ((rx_buffer.pop().unwrap() as u32) <<  0) |
+                            ((rx_buffer.pop().unwrap() as u32) <<  7) |
+                            ((rx_buffer.pop().unwrap() as u32) << 14) |
+                            ((rx_buffer.pop().unwrap() as u32) << 21) |
+                            ((rx_buffer.pop().unwrap() as u32) << 28);
+                        Ok(Some(Self::RunComplete { bangs }))
+                    },
+                    REMOTE_MESSAGE_ID_RAMP_PROFILE_COMMITTED => {
+                        let point_count = rx_buffer.pop().unwrap();
+                        Ok(Some(Self::RampProfileCommitted { point_count }))
+                    },
+                    REMOTE_MESSAGE_ID_COMMAND_REJECTED => {
+                        let message_id = rx_buffer.pop().unwrap();
+                        let reason = RejectReason::try_from(rx_buffer.pop().unwrap())?;
+                        Ok(Some(Self::CommandRejected { message_id, reason }))
+                    },
                     _ => unreachable!()
                 }
             } else {
@@ -541,3 +1522,90 @@ impl RemoteMessage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An emergency stop with one bit flipped, then a ping that must come through intact
+    fn flipped_stream(bit: usize, address: Option<u8>) -> SerialBuffer<16> {
+        let mut stream = SerialBuffer::<16>::new();
+        let mut stop = [0u8; 5];
+        ControllerMessage::EmergencyStop.encode(&mut stop).unwrap();
+        stop[bit / 8] ^= 1 << (bit % 8);
+        stream.extend_from_slice(&stop);
+        match address {
+            Some(address) => assert!(ControllerMessage::Ping(0x0123_4567).try_send_to(address, &mut stream)),
+            None => assert!(ControllerMessage::Ping(0x0123_4567).try_send(&mut stream)),
+        }
+        stream
+    }
+
+    // Whatever was received in order, skipping errors
+    fn check_received(bit: usize, received: &[Option<ControllerMessage>]) {
+        let mut received = received.iter().flatten();
+        assert!(matches!(received.next(), Some(ControllerMessage::EmergencyStop)), "bit {bit}");
+        assert!(matches!(received.next(), Some(ControllerMessage::Ping(0x0123_4567))), "bit {bit}");
+        assert!(received.next().is_none(), "bit {bit}");
+    }
+
+    #[test]
+    fn emergency_stop_survives_any_single_bit_error() {
+        for bit in 0..5 * 8 {
+            let mut rx_buffer = flipped_stream(bit, None);
+            let mut bytes = [0u8; 16];
+            let length = flipped_stream(bit, None).read_into(&mut bytes);
+            let stream = &bytes[..length];
+
+            let mut received = [None, None, None, None];
+            for slot in &mut received {
+                *slot = ControllerMessage::try_receive(&mut rx_buffer).ok().flatten();
+            }
+            check_received(bit, &received);
+
+            let mut received = [None, None, None, None];
+            let mut at = 0;
+            for slot in &mut received {
+                match ControllerMessage::decode(&stream[at..]) {
+                    Ok((message, consumed)) => {
+                        *slot = Some(message);
+                        at += consumed;
+                    },
+                    Err(DecodeError::Invalid { consumed }) => at += consumed,
+                    Err(DecodeError::Incomplete { .. }) => break,
+                }
+            }
+            check_received(bit, &received);
+
+            let mut decoder = Decoder::<ControllerMessage>::new();
+            let mut received = [None, None, None, None, None, None, None, None, None, None];
+            for (slot, byte) in received.iter_mut().zip(stream) {
+                *slot = decoder.feed(*byte).and_then(Result::ok);
+            }
+            check_received(bit, &received);
+
+            let filter = AddressFilter::new(1);
+            let mut rx_buffer = flipped_stream(bit, Some(1));
+            let mut received = [None, None, None, None];
+            for slot in &mut received {
+                *slot = filter.try_receive(&mut rx_buffer).ok().flatten().map(|(message, _)| message);
+            }
+            check_received(bit, &received);
+        }
+    }
+
+    #[test]
+    fn fire_burst_counts_over_14_bits_are_refused() {
+        let mut frame = [0u8; MAX_FRAME_LENGTH];
+        assert!(ControllerMessage::FireBurst { count: MAX_BURST_COUNT + 1 }.encode(&mut frame).is_err());
+        assert!(ControllerMessage::FireBurst { count: 20000 }.encode(&mut frame).is_err());
+
+        let length = ControllerMessage::FireBurst { count: MAX_BURST_COUNT }.encode(&mut frame).unwrap();
+        let decoded = ControllerMessage::decode(&frame[..length]);
+        assert!(matches!(decoded, Ok((ControllerMessage::FireBurst { count: MAX_BURST_COUNT }, 3))));
+
+        let mut runs = RunControl::new(RunLimits { max_burst_count: u16::MAX, max_duration_ms: 1000 });
+        assert_eq!(runs.start(&ControllerMessage::FireBurst { count: MAX_BURST_COUNT + 1 }, 0), Err(RejectReason::OutOfRange));
+        assert_eq!(runs.start(&ControllerMessage::FireBurst { count: MAX_BURST_COUNT }, 0), Ok(true));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{ControllerMessage, PARAMETER_COUNT, Parameter, ParameterSet, ParameterValue, RejectReason, RemoteMessage, RunMode};
+
+/// Lowest and highest value allowed for one parameter. Both values are of the same parameter.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParameterLimit {
+    pub min: ParameterValue,
+    pub max: ParameterValue,
+}
+
+impl ParameterLimit {
+    /// The full range the wire encoding can carry
+    pub fn unrestricted(parameter: Parameter) -> Self {
+        let (min, max) = match parameter {
+            Parameter::DelayCompensation => (ParameterValue::DelayCompensationNS(-0x2000),    ParameterValue::DelayCompensationNS(0x1FFF)),
+            Parameter::StartupFrequency  => (ParameterValue::StartupFrequencykHz(0.0),        ParameterValue::StartupFrequencykHz(0x3FFF as f32 / 16.0)),
+            Parameter::LockRange         => (ParameterValue::LockRangekHz(0.0),               ParameterValue::LockRangekHz(0x3FFF as f32 / 16.0)),
+            Parameter::RunMode           => (ParameterValue::RunMode(RunMode::OpenLoop),      ParameterValue::RunMode(RunMode::CustomRamp)),
+            Parameter::LockTime          => (ParameterValue::LockTimeUs(0),                   ParameterValue::LockTimeUs(0x3FFF)),
+            Parameter::StartupTime       => (ParameterValue::StartupTimeUs(0),                ParameterValue::StartupTimeUs(0x3FFF)),
+            Parameter::OnTime            => (ParameterValue::OnTimeUs(0),                     ParameterValue::OnTimeUs(u16::MAX / 10 * 10)),
+            Parameter::OffTime           => (ParameterValue::OffTimeMs(0),                    ParameterValue::OffTimeMs(0x3FFF)),
+            Parameter::RampStartPower    => (ParameterValue::RampStartPower(0.0),             ParameterValue::RampStartPower(1.0)),
+            Parameter::RampEndPower      => (ParameterValue::RampEndPower(0.0),               ParameterValue::RampEndPower(1.0)),
+            Parameter::MinLockCurrent    => (ParameterValue::MinLockCurrentA(0.0),            ParameterValue::MinLockCurrentA(0x3FFF as f32 / 256.0)),
+            Parameter::CurrentLimit      => (ParameterValue::CurrentLimitA(0.0),              ParameterValue::CurrentLimitA(0x3FFF as f32 / 32.0)),
+            Parameter::FlatPower         => (ParameterValue::FlatPower(0.0),                  ParameterValue::FlatPower(1.0)),
+        };
+        Self { min, max }
+    }
+
+    pub fn parameter(&self) -> Parameter {
+        self.min.parameter()
+    }
+
+    pub fn contains(&self, value: ParameterValue) -> bool {
+        value.parameter() == self.parameter() &&
+            value.as_f32() >= self.min.as_f32() &&
+            value.as_f32() <= self.max.as_f32()
+    }
+
+    /// The nearest value within the limit. `value` must be of the same parameter.
+    pub fn clamp(&self, value: ParameterValue) -> ParameterValue {
+        if value.as_f32() < self.min.as_f32() {
+            self.min
+        } else if value.as_f32() > self.max.as_f32() {
+            self.max
+        } else {
+            value
+        }
+    }
+}
+
+/// Remote side: the installation's per-parameter caps. Anyone can read them, but changing them
+/// needs an `EngineeringUnlock` with the right PIN first. After `max_attempts` wrong PINs in a row
+/// every unlock is refused for `lockout_ms`.
+pub struct LimitProfile {
+    limits: [ParameterLimit; PARAMETER_COUNT],
+    pin: u32,
+    max_attempts: u8,
+    lockout_ms: u32,
+    unlocked: bool,
+    failed_attempts: u8,
+    locked_out_since_ms: Option<u32>,
+}
+
+impl LimitProfile {
+    pub fn new(pin: u32, max_attempts: u8, lockout_ms: u32) -> Self {
+        Self {
+            limits: Parameter::ALL.map(ParameterLimit::unrestricted),
+            pin,
+            max_attempts,
+            lockout_ms,
+            unlocked: false,
+            failed_attempts: 0,
+            locked_out_since_ms: None,
+        }
+    }
+
+    pub fn limit(&self, parameter: Parameter) -> ParameterLimit {
+        self.limits[parameter.index()]
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.unlocked
+    }
+
+    pub fn unlock(&mut self, pin: u32, now_ms: u32) -> Result<(), RejectReason> {
+        if let Some(locked_out_since_ms) = self.locked_out_since_ms {
+            if now_ms.wrapping_sub(locked_out_since_ms) < self.lockout_ms {
+                return Err(RejectReason::LockedOut);
+            }
+            self.locked_out_since_ms = None;
+            self.failed_attempts = 0;
+        }
+        if pin == self.pin {
+            self.unlocked = true;
+            self.failed_attempts = 0;
+            Ok(())
+        } else {
+            self.unlocked = false;
+            self.failed_attempts = self.failed_attempts.saturating_add(1);
+            if self.failed_attempts >= self.max_attempts {
+                self.locked_out_since_ms = Some(now_ms);
+            }
+            Err(RejectReason::WrongPin)
+        }
+    }
+
+    pub fn lock(&mut self) {
+        self.unlocked = false;
+    }
+
+    pub fn set_limit(&mut self, limit: ParameterLimit) -> Result<(), RejectReason> {
+        if !self.unlocked {
+            return Err(RejectReason::Locked);
+        }
+        if limit.min.parameter() != limit.max.parameter() || limit.min.as_f32() > limit.max.as_f32() {
+            return Err(RejectReason::OutOfRange);
+        }
+        self.limits[limit.parameter().index()] = limit;
+        Ok(())
+    }
+
+    pub fn check(&self, value: ParameterValue) -> Result<(), RejectReason> {
+        if self.limit(value.parameter()).contains(value) {
+            Ok(())
+        } else {
+            Err(RejectReason::OutOfRange)
+        }
+    }
+
+    pub fn check_set(&self, set: &ParameterSet) -> Result<(), RejectReason> {
+        set.values().try_for_each(|value| self.check(value))
+    }
+
+    /// Checks a `SetParam` against the caps; every other message passes. Fits the `validate`
+    /// argument of `dispatch`; a `Dispatcher` enforces the caps of `RemoteHandler::limit_profile`
+    /// itself.
+    pub fn check_message(&self, message: &ControllerMessage) -> Result<(), RejectReason> {
+        match message {
+            ControllerMessage::SetParam(value) => self.check(*value),
+            _ => Ok(()),
+        }
+    }
+
+    /// Handles the limit related messages, returning the response to send. Returns `None` for
+    /// any other message.
+    pub fn handle(&mut self, message: &ControllerMessage, now_ms: u32) -> Option<Result<RemoteMessage, RejectReason>> {
+        match message {
+            ControllerMessage::GetLimit(parameter) => Some(Ok(RemoteMessage::LimitResult(self.limit(*parameter)))),
+            ControllerMessage::SetLimit(limit) => Some(self.set_limit(*limit).map(|_| RemoteMessage::LimitResult(*limit))),
+            ControllerMessage::EngineeringUnlock { pin } => Some(self.unlock(*pin, now_ms).map(|_| RemoteMessage::EngineeringUnlocked)),
+            ControllerMessage::EngineeringLock => {
+                self.lock();
+                Some(Ok(RemoteMessage::EngineeringLocked))
+            },
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use crate::ControllerMessage;
+
+/// Most tracks a `MidiFile` can hold
+pub const MIDI_MAX_TRACKS: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MidiError {
+    /// Doesn't start with an `MThd` header
+    NotMidi,
+    /// A chunk or event runs past the end of the data
+    Truncated,
+    /// Format 2 files (independent sequences) can't be merged into one stream
+    UnsupportedFormat,
+    TooManyTracks,
+    /// An event without a status byte and no running status to use
+    MissingStatus,
+    /// An SMPTE division with a frame rate of 0 or -128
+    InvalidDivision,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Division {
+    TicksPerQuarter(u32),
+    TicksPerSecond { frames_per_second_x100: u32, ticks_per_frame: u32 },
+}
+
+/// Host side: a Standard MIDI File, borrowed from its bytes. `messages` plays all tracks merged
+/// together as `NoteOn`, `NoteOff` and `AllNotesOff` messages with their times.
+pub struct MidiFile<'a> {
+    division: Division,
+    tracks: [&'a [u8]; MIDI_MAX_TRACKS],
+    track_count: usize,
+}
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16, MidiError> {
+    data.get(at..at + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]])).ok_or(MidiError::Truncated)
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, MidiError> {
+    data.get(at..at + 4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).ok_or(MidiError::Truncated)
+}
+
+impl<'a> MidiFile<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, MidiError> {
+        if data.get(0..4) != Some(b"MThd") {
+            return Err(MidiError::NotMidi);
+        }
+        let header_length = read_u32(data, 4)? as usize;
+        if header_length < 6 {
+            return Err(MidiError::Truncated);
+        }
+        let format = read_u16(data, 8)?;
+        let track_count = read_u16(data, 10)? as usize;
+        let division = read_u16(data, 12)?;
+        if format > 1 {
+            return Err(MidiError::UnsupportedFormat);
+        }
+        if track_count > MIDI_MAX_TRACKS {
+            return Err(MidiError::TooManyTracks);
+        }
+        let division = if division & 0x8000 == 0 {
+            Division::TicksPerQuarter((division as u32).max(1))
+        } else {
+            // Negative SMPTE frame rate in the high byte; -29 means 29.97 drop frame
+            let frames_per_second = match ((division >> 8) as u8 as i8).checked_neg() {
+                Some(frames_per_second) if frames_per_second > 0 => frames_per_second as u32,
+                _ => return Err(MidiError::InvalidDivision),
+            };
+            Division::TicksPerSecond {
+                frames_per_second_x100: if frames_per_second == 29 { 2997 } else { frames_per_second * 100 },
+                ticks_per_frame: (division & 0xFF) as u32,
+            }
+        };
+        let mut tracks: [&'a [u8]; MIDI_MAX_TRACKS] = [&[]; MIDI_MAX_TRACKS];
+        let mut found = 0;
+        let mut at = header_length.checked_add(8).ok_or(MidiError::Truncated)?;
+        while found < track_count {
+            let chunk_type = data.get(at..at + 4).ok_or(MidiError::Truncated)?;
+            let length = read_u32(data, at + 4)? as usize;
+            let end = (at + 8).checked_add(length).ok_or(MidiError::Truncated)?;
+            let body = data.get(at + 8..end).ok_or(MidiError::Truncated)?;
+            // Unknown chunk types are skipped, as the spec asks
+            if chunk_type == b"MTrk" {
+                tracks[found] = body;
+                found += 1;
+            }
+            at = end;
+        }
+        Ok(Self {
+            division,
+            tracks,
+            track_count,
+        })
+    }
+
+    pub fn track_count(&self) -> usize {
+        self.track_count
+    }
+
+    pub fn messages(&self) -> MidiMessages<'a> {
+        let mut cursors = [TrackCursor::ENDED; MIDI_MAX_TRACKS];
+        let mut error = None;
+        for (cursor, track) in cursors.iter_mut().zip(&self.tracks[..self.track_count]) {
+            *cursor = TrackCursor { data: track, at: 0, tick: 0, running_status: 0, ended: false };
+            if let Err(e) = cursor.read_delta() {
+                error = Some(e);
+            }
+        }
+        MidiMessages {
+            division: self.division,
+            cursors,
+            us_per_quarter: 500_000,
+            tempo_tick: 0,
+            tempo_time_us: 0,
+            error,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct TrackCursor<'a> {
+    data: &'a [u8],
+    at: usize,
+    /// Absolute tick of the next event
+    tick: u64,
+    running_status: u8,
+    ended: bool,
+}
+
+impl<'a> TrackCursor<'a> {
+    const ENDED: Self = Self { data: &[], at: 0, tick: 0, running_status: 0, ended: true };
+
+    fn byte(&mut self) -> Result<u8, MidiError> {
+        let byte = *self.data.get(self.at).ok_or(MidiError::Truncated)?;
+        self.at += 1;
+        Ok(byte)
+    }
+
+    fn variable_length(&mut self) -> Result<u32, MidiError> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        Ok(value)
+    }
+
+    fn skip(&mut self, length: usize) -> Result<&'a [u8], MidiError> {
+        let end = self.at.checked_add(length).ok_or(MidiError::Truncated)?;
+        let bytes = self.data.get(self.at..end).ok_or(MidiError::Truncated)?;
+        self.at = end;
+        Ok(bytes)
+    }
+
+    /// Reads the delta time before the next event, or ends the track if there is none
+    fn read_delta(&mut self) -> Result<(), MidiError> {
+        if self.at >= self.data.len() {
+            self.ended = true;
+            return Ok(());
+        }
+        self.tick += self.variable_length()? as u64;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TimedMessage {
+    /// Microseconds from the start of the file
+    pub time_us: u64,
+    pub message: ControllerMessage,
+}
+
+/// Iterator over a `MidiFile`'s note events in time order. Stops after yielding an error.
+pub struct MidiMessages<'a> {
+    division: Division,
+    cursors: [TrackCursor<'a>; MIDI_MAX_TRACKS],
+    us_per_quarter: u32,
+    tempo_tick: u64,
+    tempo_time_us: u64,
+    error: Option<MidiError>,
+}
+
+enum MidiEvent {
+    Message(ControllerMessage),
+    Tempo(u32),
+    None,
+}
+
+impl MidiMessages<'_> {
+    fn time_us(&self, tick: u64) -> u64 {
+        match self.division {
+            Division::TicksPerQuarter(ticks_per_quarter) =>
+                self.tempo_time_us + (tick - self.tempo_tick) * self.us_per_quarter as u64 / ticks_per_quarter as u64,
+            Division::TicksPerSecond { frames_per_second_x100, ticks_per_frame } =>
+                tick * 100_000_000 / (frames_per_second_x100 as u64 * ticks_per_frame.max(1) as u64),
+        }
+    }
+
+    fn read_event(cursor: &mut TrackCursor) -> Result<MidiEvent, MidiError> {
+        let mut status = cursor.byte()?;
+        if status & 0x80 == 0 {
+            // Running status: this byte is already the first data byte
+            if cursor.running_status == 0 {
+                return Err(MidiError::MissingStatus);
+            }
+            cursor.at -= 1;
+            status = cursor.running_status;
+        }
+        match status {
+            0xFF => {
+                let meta_type = cursor.byte()?;
+                let length = cursor.variable_length()? as usize;
+                let data = cursor.skip(length)?;
+                match meta_type {
+                    0x2F => cursor.ended = true,
+                    0x51 if length == 3 => return Ok(MidiEvent::Tempo(((data[0] as u32) << 16) | ((data[1] as u32) << 8) | data[2] as u32)),
+                    _ => {},
+                }
+                Ok(MidiEvent::None)
+            },
+            0xF0 | 0xF7 => {
+                let length = cursor.variable_length()? as usize;
+                cursor.skip(length)?;
+                Ok(MidiEvent::None)
+            },
+            0xF1..=0xFE => Err(MidiError::MissingStatus),
+            _ => {
+                cursor.running_status = status;
+                let first = cursor.byte()? & 0x7F;
+                let second = match status & 0xF0 {
+                    0xC0 | 0xD0 => 0,
+                    _ => cursor.byte()? & 0x7F,
+                };
+                Ok(match (status & 0xF0, first, second) {
+                    (0x80, note, _) | (0x90, note, 0) => MidiEvent::Message(ControllerMessage::NoteOff { note }),
+                    (0x90, note, velocity)            => MidiEvent::Message(ControllerMessage::NoteOn { note, velocity }),
+                    // All Sound Off and All Notes Off controllers
+                    (0xB0, 120 | 123, _)              => MidiEvent::Message(ControllerMessage::AllNotesOff),
+                    _ => MidiEvent::None,
+                })
+            },
+        }
+    }
+}
+
+impl Iterator for MidiMessages<'_> {
+    type Item = Result<TimedMessage, MidiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.error.take() {
+            self.cursors = [TrackCursor::ENDED; MIDI_MAX_TRACKS];
+            return Some(Err(error));
+        }
+        loop {
+            let cursor = self.cursors.iter_mut().filter(|cursor| !cursor.ended).min_by_key(|cursor| cursor.tick)?;
+            let tick = cursor.tick;
+            let event = Self::read_event(cursor).and_then(|event| {
+                if !cursor.ended {
+                    cursor.read_delta()?;
+                }
+                Ok(event)
+            });
+            let event = match event {
+                Ok(event) => event,
+                Err(error) => {
+                    self.cursors = [TrackCursor::ENDED; MIDI_MAX_TRACKS];
+                    return Some(Err(error));
+                },
+            };
+            match event {
+                MidiEvent::Message(message) => return Some(Ok(TimedMessage { time_us: self.time_us(tick), message })),
+                MidiEvent::Tempo(us_per_quarter) => {
+                    self.tempo_time_us = self.time_us(tick);
+                    self.tempo_tick = tick;
+                    self.us_per_quarter = us_per_quarter;
+                },
+                MidiEvent::None => {},
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: [u8; 14] = *b"MThd\x00\x00\x00\x06\x00\x01\x00\x02\x00\x60";
+
+    // Format 1, 96 ticks per quarter. The tempo halves to 250 ms per quarter at tick 96.
+    const FILE: [u8; 61] = *b"MThd\x00\x00\x00\x06\x00\x01\x00\x02\x00\x60\
+        MTrk\x00\x00\x00\x12\
+        \x00\x90\x3C\x64\
+        \x60\xFF\x51\x03\x03\xD0\x90\
+        \x00\x3C\x00\
+        \x00\xFF\x2F\x00\
+        MTrk\x00\x00\x00\x0D\
+        \x81\x40\x90\x40\x50\
+        \x00\xB0\x7B\x00\
+        \x00\xFF\x2F\x00";
+
+    #[test]
+    fn tracks_are_merged_in_time_order() {
+        let file = MidiFile::parse(&FILE).unwrap();
+        assert_eq!(file.track_count(), 2);
+        let mut messages = file.messages();
+        assert!(matches!(messages.next(), Some(Ok(TimedMessage { time_us: 0, message: ControllerMessage::NoteOn { note: 60, velocity: 100 } }))));
+        assert!(matches!(messages.next(), Some(Ok(TimedMessage { time_us: 500_000, message: ControllerMessage::NoteOff { note: 60 } }))));
+        assert!(matches!(messages.next(), Some(Ok(TimedMessage { time_us: 750_000, message: ControllerMessage::NoteOn { note: 64, velocity: 80 } }))));
+        assert!(matches!(messages.next(), Some(Ok(TimedMessage { time_us: 750_000, message: ControllerMessage::AllNotesOff }))));
+        assert!(messages.next().is_none());
+    }
+
+    #[test]
+    fn malformed_headers_are_refused() {
+        assert_eq!(MidiFile::parse(b"RIFF\x00\x00\x00\x06").err(), Some(MidiError::NotMidi));
+
+        let mut header = HEADER;
+        header[9] = 2;
+        assert_eq!(MidiFile::parse(&header).err(), Some(MidiError::UnsupportedFormat));
+
+        let mut header = HEADER;
+        header[12] = 0x80;
+        assert_eq!(MidiFile::parse(&header).err(), Some(MidiError::InvalidDivision));
+        header[12] = 0x00;
+        assert_eq!(MidiFile::parse(&header[..13]).err(), Some(MidiError::Truncated));
+    }
+
+    #[test]
+    fn chunks_running_past_the_end_are_truncated() {
+        let mut file = [0u8; 22];
+        file[..14].copy_from_slice(&HEADER);
+        file[14..].copy_from_slice(b"MTrk\xFF\xFF\xFF\xFF");
+        assert_eq!(MidiFile::parse(&file).err(), Some(MidiError::Truncated));
+
+        // The length fits, but the second track is missing
+        assert_eq!(MidiFile::parse(&FILE[..14 + 8 + 0x12]).err(), Some(MidiError::Truncated));
+    }
+
+    #[test]
+    fn an_event_without_a_status_ends_the_messages() {
+        let mut file = [0u8; 26];
+        file[..14].copy_from_slice(&HEADER);
+        file[11] = 1;
+        file[14..].copy_from_slice(b"MTrk\x00\x00\x00\x04\x00\x3C\x64\x00");
+        let mut messages = MidiFile::parse(&file).unwrap().messages();
+        assert!(matches!(messages.next(), Some(Err(MidiError::MissingStatus))));
+        assert!(messages.next().is_none());
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{ControllerMessage, PARAMETER_COUNT, ParameterValue, RemoteMessage};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParamMismatch {
+    pub requested: ParameterValue,
+    pub applied: ParameterValue,
+}
+
+/// Controller side: remembers the value most recently requested for each parameter and compares
+/// it with the `RemoteMessage::ParamApplied` the remote answers with. Values sent inside a
+/// transaction aren't answered, so they aren't tracked.
+pub struct ParamApplyTracker {
+    pending: [Option<ParameterValue>; PARAMETER_COUNT],
+    in_transaction: bool,
+}
+
+impl ParamApplyTracker {
+    pub fn new() -> Self {
+        Self {
+            pending: [None; PARAMETER_COUNT],
+            in_transaction: false,
+        }
+    }
+
+    pub fn on_sent(&mut self, message: &ControllerMessage) {
+        match message {
+            ControllerMessage::SetParam(value) if !self.in_transaction => {
+                self.pending[value.parameter().index()] = Some(*value);
+            },
+            ControllerMessage::BeginTransaction => self.in_transaction = true,
+            ControllerMessage::Commit | ControllerMessage::Abort => self.in_transaction = false,
+            _ => {},
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.iter().any(Option::is_some)
+    }
+
+    /// Returns the mismatch if `message` is a `ParamApplied` whose value is further than the
+    /// parameter's resolution from what was requested.
+    pub fn handle(&mut self, message: &RemoteMessage) -> Option<ParamMismatch> {
+        if let RemoteMessage::ParamApplied(applied) = message {
+            let parameter = applied.parameter();
+            let requested = self.pending[parameter.index()].take()?;
+            let difference = (requested.as_f32() - applied.as_f32()).abs();
+            if difference > parameter.resolution() {
+                return Some(ParamMismatch {
+                    requested,
+                    applied: *applied,
+                });
+            }
+        }
+        None
+    }
+}
+
+impl Default for ParamApplyTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
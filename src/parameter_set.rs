@@ -0,0 +1,99 @@
+use crate::{Parameter, ParameterValue, RunMode};
+
+/// One value for every `Parameter`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParameterSet {
+    pub delay_compensation_ns: i16,
+    pub startup_frequency_khz: f32,
+    pub lock_range_khz: f32,
+    pub run_mode: RunMode,
+    pub lock_time_us: u16,
+    pub startup_time_us: u16,
+    pub on_time_us: u16,
+    pub off_time_ms: u16,
+    pub ramp_start_power: f32,
+    pub ramp_end_power: f32,
+    pub min_lock_current_a: f32,
+    pub current_limit_a: f32,
+    pub flat_power: f32,
+}
+
+impl ParameterSet {
+    /// Conservative starting values: open loop at low power and duty cycle
+    pub const DEFAULT: Self = Self {
+        delay_compensation_ns: 0,
+        startup_frequency_khz: 400.0,
+        lock_range_khz: 50.0,
+        run_mode: RunMode::OpenLoop,
+        lock_time_us: 50,
+        startup_time_us: 20,
+        on_time_us: 1000,
+        off_time_ms: 500,
+        ramp_start_power: 0.1,
+        ramp_end_power: 0.5,
+        min_lock_current_a: 5.0,
+        current_limit_a: 100.0,
+        flat_power: 0.1,
+    };
+
+    pub fn get(&self, parameter: Parameter) -> ParameterValue {
+        match parameter {
+            Parameter::DelayCompensation => ParameterValue::DelayCompensationNS(self.delay_compensation_ns),
+            Parameter::StartupFrequency  => ParameterValue::StartupFrequencykHz(self.startup_frequency_khz),
+            Parameter::LockRange         => ParameterValue::LockRangekHz(self.lock_range_khz),
+            Parameter::RunMode           => ParameterValue::RunMode(self.run_mode),
+            Parameter::LockTime          => ParameterValue::LockTimeUs(self.lock_time_us),
+            Parameter::StartupTime       => ParameterValue::StartupTimeUs(self.startup_time_us),
+            Parameter::OnTime            => ParameterValue::OnTimeUs(self.on_time_us),
+            Parameter::OffTime           => ParameterValue::OffTimeMs(self.off_time_ms),
+            Parameter::RampStartPower    => ParameterValue::RampStartPower(self.ramp_start_power),
+            Parameter::RampEndPower      => ParameterValue::RampEndPower(self.ramp_end_power),
+            Parameter::MinLockCurrent    => ParameterValue::MinLockCurrentA(self.min_lock_current_a),
+            Parameter::CurrentLimit      => ParameterValue::CurrentLimitA(self.current_limit_a),
+            Parameter::FlatPower         => ParameterValue::FlatPower(self.flat_power),
+        }
+    }
+
+    pub fn set(&mut self, value: ParameterValue) {
+        match value {
+            ParameterValue::DelayCompensationNS(delay_ns)      => self.delay_compensation_ns = delay_ns,
+            ParameterValue::StartupFrequencykHz(frequency_khz) => self.startup_frequency_khz = frequency_khz,
+            ParameterValue::LockRangekHz(frequency_khz)        => self.lock_range_khz = frequency_khz,
+            ParameterValue::RunMode(run_mode)                  => self.run_mode = run_mode,
+            ParameterValue::LockTimeUs(time)                   => self.lock_time_us = time,
+            ParameterValue::StartupTimeUs(time)                => self.startup_time_us = time,
+            ParameterValue::OnTimeUs(time)                     => self.on_time_us = time,
+            ParameterValue::OffTimeMs(time)                    => self.off_time_ms = time,
+            ParameterValue::RampStartPower(power)              => self.ramp_start_power = power,
+            ParameterValue::RampEndPower(power)                => self.ramp_end_power = power,
+            ParameterValue::MinLockCurrentA(current)           => self.min_lock_current_a = current,
+            ParameterValue::CurrentLimitA(current)             => self.current_limit_a = current,
+            ParameterValue::FlatPower(power)                   => self.flat_power = power,
+        }
+    }
+
+    pub fn with(mut self, value: ParameterValue) -> Self {
+        self.set(value);
+        self
+    }
+
+    /// Fraction of each bang period spent on, `OnTimeUs / (OnTimeUs + OffTimeMs)`
+    pub fn duty_cycle(&self) -> f32 {
+        let on_time_us = self.on_time_us as f32;
+        let period_us = on_time_us + self.off_time_ms as f32 * 1000.0;
+        if period_us > 0.0 {
+            on_time_us / period_us
+        } else {
+            0.0
+        }
+    }
+
+    /// Upper bound on the energy of one bang, `CurrentLimitA * OnTimeUs`, in A·µs
+    pub fn energy_amp_microseconds(&self) -> f32 {
+        self.current_limit_a * self.on_time_us as f32
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = ParameterValue> + '_ {
+        Parameter::ALL.iter().map(|parameter| self.get(*parameter))
+    }
+}
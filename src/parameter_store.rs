@@ -0,0 +1,340 @@
+use crate::{
+    ControllerMessage, PARAMETER_COUNT, Parameter, ParameterLimit, ParameterSet, ParameterValue, RejectReason, RemoteMessage,
+    RunMode,
+};
+
+const PARAMETER_BLOB_VERSION: u8 = 1;
+
+/// Length of the blob written by `ParameterStore::save`: version, parameter count, two bytes per
+/// parameter and a CRC-16
+pub const PARAMETER_BLOB_LENGTH: usize = 2 + PARAMETER_COUNT * 2 + 2;
+
+// CRC-16/CCITT-FALSE
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if (crc & 0x8000) != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn quantize(value: ParameterValue) -> ParameterValue {
+    let raw: (Parameter, u16) = value.into();
+    ParameterValue::try_from(raw).unwrap_or(value)
+}
+
+/// Remote side: the current value of every parameter, starting from a set of defaults. Values are
+/// checked on the way in, and each parameter that changes is flagged until `take_changes` so the
+/// firmware knows what to reapply.
+///
+/// Values are kept as the wire encoding carries them, the same as a value received in a `SetParam`,
+/// so `get` reports exactly what's in use. `save` and `load` persist them as a checksummed blob,
+/// e.g. in flash.
+pub struct ParameterStore {
+    defaults: ParameterSet,
+    values: ParameterSet,
+    changed: u16,
+    unsaved: bool,
+}
+
+impl ParameterStore {
+    /// Starts from `ParameterSet::DEFAULT`
+    pub fn new() -> Self {
+        Self::with_defaults(ParameterSet::DEFAULT)
+    }
+
+    pub fn with_defaults(defaults: ParameterSet) -> Self {
+        let mut quantized = defaults;
+        for value in defaults.values() {
+            quantized.set(quantize(value));
+        }
+        let defaults = quantized;
+        Self {
+            defaults,
+            values: defaults,
+            changed: 0,
+            unsaved: false,
+        }
+    }
+
+    pub fn parameters(&self) -> &ParameterSet {
+        &self.values
+    }
+
+    pub fn defaults(&self) -> &ParameterSet {
+        &self.defaults
+    }
+
+    pub fn get(&self, param: Parameter) -> ParameterValue {
+        self.values.get(param)
+    }
+
+    /// Checks that the value is within the range the wire encoding can carry
+    pub fn validate(value: ParameterValue) -> Result<(), RejectReason> {
+        if ParameterLimit::unrestricted(value.parameter()).contains(value) {
+            Ok(())
+        } else {
+            Err(RejectReason::OutOfRange)
+        }
+    }
+
+    pub fn set(&mut self, value: ParameterValue) -> Result<(), RejectReason> {
+        Self::validate(value)?;
+        self.store(value);
+        Ok(())
+    }
+
+    /// Sets every parameter, all or none, failing on the first invalid value
+    pub fn set_all(&mut self, set: &ParameterSet) -> Result<(), RejectReason> {
+        set.values().try_for_each(Self::validate)?;
+        for value in set.values() {
+            self.store(value);
+        }
+        Ok(())
+    }
+
+    pub fn reset_to_defaults(&mut self) {
+        let defaults = self.defaults;
+        for value in defaults.values() {
+            self.store(value);
+        }
+    }
+
+    fn store(&mut self, value: ParameterValue) {
+        let value = quantize(value);
+        let param = value.parameter();
+        if self.values.get(param) != value {
+            self.values.set(value);
+            self.changed |= 1 << param.index();
+            self.unsaved = true;
+        }
+    }
+
+    pub fn is_changed(&self, param: Parameter) -> bool {
+        (self.changed & (1 << param.index())) != 0
+    }
+
+    pub fn has_changes(&self) -> bool {
+        self.changed != 0
+    }
+
+    /// Returns the parameters changed since the last call and clears their flags
+    pub fn take_changes(&mut self) -> impl Iterator<Item = Parameter> + use<> {
+        let changed = self.changed;
+        self.changed = 0;
+        Parameter::ALL.into_iter().filter(move |param| (changed & (1 << param.index())) != 0)
+    }
+
+    /// Some value changed since the last `save` or `load`
+    pub fn is_unsaved(&self) -> bool {
+        self.unsaved
+    }
+
+    pub fn save(&mut self) -> [u8; PARAMETER_BLOB_LENGTH] {
+        let mut blob = [0u8; PARAMETER_BLOB_LENGTH];
+        blob[0] = PARAMETER_BLOB_VERSION;
+        blob[1] = PARAMETER_COUNT as u8;
+        for (i, value) in self.values.values().enumerate() {
+            let (_, raw): (Parameter, u16) = value.into();
+            blob[2 + i * 2..4 + i * 2].copy_from_slice(&raw.to_le_bytes());
+        }
+        let crc = crc16(&blob[..PARAMETER_BLOB_LENGTH - 2]);
+        blob[PARAMETER_BLOB_LENGTH - 2..].copy_from_slice(&crc.to_le_bytes());
+        self.unsaved = false;
+        blob
+    }
+
+    /// Replaces every value with those in a blob from `save`. Fails, leaving the values as they
+    /// were, if the blob is the wrong length or version, fails its checksum or holds an invalid
+    /// value.
+    #[allow(clippy::result_unit_err)]
+    pub fn load(&mut self, blob: &[u8]) -> Result<(), ()> {
+        if blob.len() != PARAMETER_BLOB_LENGTH || blob[0] != PARAMETER_BLOB_VERSION || blob[1] != PARAMETER_COUNT as u8 {
+            return Err(());
+        }
+        let crc = u16::from_le_bytes([blob[PARAMETER_BLOB_LENGTH - 2], blob[PARAMETER_BLOB_LENGTH - 1]]);
+        if crc != crc16(&blob[..PARAMETER_BLOB_LENGTH - 2]) {
+            return Err(());
+        }
+        let mut set = self.values;
+        for (i, param) in Parameter::ALL.into_iter().enumerate() {
+            let raw = u16::from_le_bytes([blob[2 + i * 2], blob[3 + i * 2]]);
+            set.set(ParameterValue::try_from((param, raw))?);
+        }
+        self.set_all(&set).map_err(|_| ())?;
+        self.unsaved = false;
+        Ok(())
+    }
+
+    /// Answers `GetParam` and applies `SetParam`, returning the response to send. Returns `None`
+    /// for any other message. Knows nothing of transactions, so a `SetParam` inside one should be
+    /// staged in a `Transaction` instead of passed here.
+    pub fn handle(&mut self, message: &ControllerMessage) -> Option<Result<RemoteMessage, RejectReason>> {
+        match message {
+            ControllerMessage::GetParam(param) => Some(Ok(RemoteMessage::GetParamResult(self.get(*param)))),
+            ControllerMessage::SetParam(value) => Some(self.set(*value).map(|_| RemoteMessage::ParamApplied(self.get(value.parameter())))),
+            _ => None,
+        }
+    }
+
+    pub fn delay_compensation_ns(&self) -> i16 {
+        self.values.delay_compensation_ns
+    }
+
+    pub fn startup_frequency_khz(&self) -> f32 {
+        self.values.startup_frequency_khz
+    }
+
+    pub fn lock_range_khz(&self) -> f32 {
+        self.values.lock_range_khz
+    }
+
+    pub fn run_mode(&self) -> RunMode {
+        self.values.run_mode
+    }
+
+    pub fn lock_time_us(&self) -> u16 {
+        self.values.lock_time_us
+    }
+
+    pub fn startup_time_us(&self) -> u16 {
+        self.values.startup_time_us
+    }
+
+    pub fn on_time_us(&self) -> u16 {
+        self.values.on_time_us
+    }
+
+    pub fn off_time_ms(&self) -> u16 {
+        self.values.off_time_ms
+    }
+
+    pub fn ramp_start_power(&self) -> f32 {
+        self.values.ramp_start_power
+    }
+
+    pub fn ramp_end_power(&self) -> f32 {
+        self.values.ramp_end_power
+    }
+
+    pub fn min_lock_current_a(&self) -> f32 {
+        self.values.min_lock_current_a
+    }
+
+    pub fn current_limit_a(&self) -> f32 {
+        self.values.current_limit_a
+    }
+
+    pub fn flat_power(&self) -> f32 {
+        self.values.flat_power
+    }
+}
+
+impl Default for ParameterStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed_store() -> ParameterStore {
+        let mut store = ParameterStore::new();
+        assert_eq!(store.set(ParameterValue::OnTimeUs(200)), Ok(()));
+        assert_eq!(store.set(ParameterValue::StartupFrequencykHz(412.53)), Ok(()));
+        assert_eq!(store.set(ParameterValue::RunMode(RunMode::Interrupter)), Ok(()));
+        store
+    }
+
+    // Puts a valid checksum back on a modified blob
+    fn reseal(blob: &mut [u8; PARAMETER_BLOB_LENGTH]) {
+        let crc = crc16(&blob[..PARAMETER_BLOB_LENGTH - 2]);
+        blob[PARAMETER_BLOB_LENGTH - 2..].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    #[test]
+    fn saved_values_load_back() {
+        let mut saved = changed_store();
+        assert!(saved.is_unsaved());
+        let blob = saved.save();
+        assert!(!saved.is_unsaved());
+
+        let mut store = ParameterStore::new();
+        assert_eq!(store.load(&blob), Ok(()));
+        assert_eq!(store.parameters(), saved.parameters());
+        assert_eq!(store.startup_frequency_khz(), 412.5);
+        assert!(!store.is_unsaved());
+        let mut changes = store.take_changes();
+        assert_eq!(changes.next(), Some(Parameter::StartupFrequency));
+        assert_eq!(changes.next(), Some(Parameter::RunMode));
+        assert_eq!(changes.next(), Some(Parameter::OnTime));
+        assert_eq!(changes.next(), None);
+    }
+
+    #[test]
+    fn corrupt_blobs_leave_the_values_alone() {
+        let blob = changed_store().save();
+        let mut store = ParameterStore::new();
+        let check = |store: &mut ParameterStore, blob: &[u8]| {
+            assert_eq!(store.load(blob), Err(()));
+            assert_eq!(store.parameters(), store.defaults());
+            assert!(!store.has_changes());
+        };
+
+        let mut corrupt = blob;
+        corrupt[2 + Parameter::OnTime.index() * 2] ^= 0x01;
+        check(&mut store, &corrupt);
+
+        check(&mut store, &blob[..PARAMETER_BLOB_LENGTH - 1]);
+
+        let mut corrupt = blob;
+        corrupt[0] = PARAMETER_BLOB_VERSION + 1;
+        reseal(&mut corrupt);
+        check(&mut store, &corrupt);
+
+        let mut corrupt = blob;
+        corrupt[2 + Parameter::RunMode.index() * 2..4 + Parameter::RunMode.index() * 2].copy_from_slice(&99u16.to_le_bytes());
+        reseal(&mut corrupt);
+        check(&mut store, &corrupt);
+
+        // A power of about 2, which the range check refuses
+        let mut corrupt = blob;
+        corrupt[2 + Parameter::FlatPower.index() * 2..4 + Parameter::FlatPower.index() * 2].copy_from_slice(&0x7FFEu16.to_le_bytes());
+        reseal(&mut corrupt);
+        check(&mut store, &corrupt);
+    }
+
+    #[test]
+    fn only_real_changes_are_flagged() {
+        let mut store = ParameterStore::new();
+        assert_eq!(store.set(ParameterValue::OnTimeUs(ParameterSet::DEFAULT.on_time_us)), Ok(()));
+        assert!(!store.has_changes() && !store.is_unsaved());
+
+        assert_eq!(store.set(ParameterValue::OnTimeUs(200)), Ok(()));
+        assert!(store.is_changed(Parameter::OnTime) && !store.is_changed(Parameter::OffTime));
+        let mut changes = store.take_changes();
+        assert_eq!((changes.next(), changes.next()), (Some(Parameter::OnTime), None));
+        assert!(!store.has_changes() && store.is_unsaved());
+
+        store.reset_to_defaults();
+        assert_eq!(store.parameters(), store.defaults());
+        assert!(store.is_changed(Parameter::OnTime));
+    }
+
+    #[test]
+    fn set_all_changes_nothing_if_any_value_is_invalid() {
+        let mut store = ParameterStore::new();
+        let set = ParameterSet::DEFAULT.with(ParameterValue::OnTimeUs(200)).with(ParameterValue::RampEndPower(1.5));
+        assert_eq!(store.set_all(&set), Err(RejectReason::OutOfRange));
+        assert_eq!(store.on_time_us(), ParameterSet::DEFAULT.on_time_us);
+        assert!(!store.has_changes());
+
+        assert_eq!(store.set_all(&set.with(ParameterValue::RampEndPower(1.0))), Ok(()));
+        assert_eq!((store.on_time_us(), store.ramp_end_power()), (200, 1.0));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{ControllerMessage, RejectReason};
+
+/// One breakpoint of a `RampProfile`: the drive power at `time_us` into the bang
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RampPoint {
+    pub time_us: u16,
+    pub power: f32,
+}
+
+impl RampPoint {
+    const ZERO: Self = Self { time_us: 0, power: 0.0 };
+}
+
+pub(crate) fn ramp_power_at(points: &[RampPoint], time_us: f32) -> f32 {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return 0.0;
+    };
+    if time_us <= first.time_us as f32 {
+        return first.power;
+    }
+    for pair in points.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        if time_us < end.time_us as f32 {
+            let fraction = (time_us - start.time_us as f32) / (end.time_us - start.time_us) as f32;
+            return start.power + (end.power - start.power) * fraction;
+        }
+    }
+    last.power
+}
+
+/// Power envelope for `RunMode::CustomRamp`, linear between breakpoints and held flat before the
+/// first and after the last. Holds at most `N` points; `N` can't go over 127 since point indices
+/// are sent as a single byte.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RampProfile<const N: usize> {
+    points: [RampPoint; N],
+    count: usize,
+}
+
+impl<const N: usize> RampProfile<N> {
+    pub fn new() -> Self {
+        const { assert!(N <= 127, "ramp point indices are sent as a single 7 bit byte") }
+        Self {
+            points: [RampPoint::ZERO; N],
+            count: 0,
+        }
+    }
+
+    /// Checks that there is at least one point, that times never go backwards or past
+    /// `on_time_us` and that every power is within 0..=1
+    pub fn validate(points: &[RampPoint], on_time_us: u16) -> Result<(), RejectReason> {
+        if points.is_empty() || points.len() > N {
+            return Err(RejectReason::InvalidRampProfile);
+        }
+        let in_order = points.windows(2).all(|pair| pair[0].time_us <= pair[1].time_us);
+        let in_bounds = points.iter().all(|point| point.time_us <= on_time_us && (0.0..=1.0).contains(&point.power));
+        if in_order && in_bounds {
+            Ok(())
+        } else {
+            Err(RejectReason::InvalidRampProfile)
+        }
+    }
+
+    pub fn from_points(points: &[RampPoint], on_time_us: u16) -> Result<Self, RejectReason> {
+        Self::validate(points, on_time_us)?;
+        let mut profile = Self::new();
+        profile.points[..points.len()].copy_from_slice(points);
+        profile.count = points.len();
+        Ok(profile)
+    }
+
+    pub fn points(&self) -> &[RampPoint] {
+        &self.points[..self.count]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Drive power `time_us` into the bang. An empty profile gives 0.
+    pub fn power_at(&self, time_us: f32) -> f32 {
+        ramp_power_at(self.points(), time_us)
+    }
+
+    /// Controller side: the messages that upload this profile, begin to commit
+    pub fn upload(&self) -> impl Iterator<Item = ControllerMessage> + '_ {
+        let points = self.points().iter().enumerate().map(|(index, point)| ControllerMessage::UploadRampPoint { index: index as u8, point: *point });
+        core::iter::once(ControllerMessage::BeginRampUpload { point_count: self.count as u8 })
+            .chain(points)
+            .chain(core::iter::once(ControllerMessage::CommitRampUpload))
+    }
+}
+
+impl<const N: usize> Default for RampProfile<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Remote side staging area for a `BeginRampUpload` .. `CommitRampUpload` sequence. A profile
+/// doesn't fit in one frame, so its points arrive one per `UploadRampPoint` and only replace the
+/// active profile once all of them are in and the whole profile validates.
+pub struct RampUpload<const N: usize> {
+    expected: Option<usize>,
+    staged: [Option<RampPoint>; N],
+}
+
+impl<const N: usize> RampUpload<N> {
+    pub fn new() -> Self {
+        const { assert!(N <= 127, "ramp point indices are sent as a single 7 bit byte") }
+        Self {
+            expected: None,
+            staged: [None; N],
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.expected.is_some()
+    }
+
+    /// Starts a new upload of `point_count` points, discarding anything staged by a previous one
+    pub fn begin(&mut self, point_count: u8) -> Result<(), RejectReason> {
+        self.expected = None;
+        self.staged = [None; N];
+        if point_count == 0 || point_count as usize > N {
+            return Err(RejectReason::OutOfRange);
+        }
+        self.expected = Some(point_count as usize);
+        Ok(())
+    }
+
+    /// Stages a point, replacing any earlier point staged at the same index
+    pub fn stage(&mut self, index: u8, point: RampPoint) -> Result<(), RejectReason> {
+        let expected = self.expected.ok_or(RejectReason::NoTransaction)?;
+        if index as usize >= expected {
+            return Err(RejectReason::OutOfRange);
+        }
+        self.staged[index as usize] = Some(point);
+        Ok(())
+    }
+
+    pub fn abort(&mut self) {
+        self.expected = None;
+        self.staged = [None; N];
+    }
+
+    /// Builds the profile from the staged points, checked against the current `OnTimeUs`. The
+    /// upload is closed either way.
+    pub fn commit(&mut self, on_time_us: u16) -> Result<RampProfile<N>, RejectReason> {
+        let expected = self.expected.ok_or(RejectReason::NoTransaction)?;
+        let staged = self.staged;
+        self.abort();
+        let mut points = [RampPoint::ZERO; N];
+        for (point, staged) in points.iter_mut().zip(&staged[..expected]) {
+            *point = staged.ok_or(RejectReason::InvalidRampProfile)?;
+        }
+        RampProfile::from_points(&points[..expected], on_time_us)
+    }
+}
+
+impl<const N: usize> Default for RampUpload<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
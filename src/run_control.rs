@@ -0,0 +1,108 @@
+use crate::{ControllerMessage, MAX_BURST_COUNT, RejectReason, RemoteMessage};
+
+/// Per installation bounds on counted and timed runs
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RunLimits {
+    /// No more than `MAX_BURST_COUNT` is ever allowed, whatever this is set to
+    pub max_burst_count: u16,
+    pub max_duration_ms: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ActiveRun {
+    Continuous,
+    Bangs { remaining: u16 },
+    Until { end_ms: u32 },
+}
+
+/// Remote side bookkeeping for `Run`, `FireSingle`, `FireBurst` and `RunFor`. The remote ends
+/// counted and timed runs itself and reports them with `RemoteMessage::RunComplete`, so they
+/// don't rely on the controller sending `Stop` at the right moment.
+pub struct RunControl {
+    limits: RunLimits,
+    active: Option<ActiveRun>,
+    bangs: u32,
+}
+
+impl RunControl {
+    pub fn new(limits: RunLimits) -> Self {
+        Self {
+            limits,
+            active: None,
+            bangs: 0,
+        }
+    }
+
+    pub fn limits(&self) -> RunLimits {
+        self.limits
+    }
+
+    /// Starts a run for `message`. Returns `Ok(false)` for messages that don't start runs.
+    pub fn start(&mut self, message: &ControllerMessage, now_ms: u32) -> Result<bool, RejectReason> {
+        let run = match message {
+            ControllerMessage::Run => {
+                if self.active.is_none() {
+                    self.bangs = 0;
+                    self.active = Some(ActiveRun::Continuous);
+                }
+                return Ok(true);
+            },
+            ControllerMessage::FireSingle => ActiveRun::Bangs { remaining: 1 },
+            ControllerMessage::FireBurst { count } => {
+                if *count == 0 || *count > self.limits.max_burst_count.min(MAX_BURST_COUNT) {
+                    return Err(RejectReason::OutOfRange);
+                }
+                ActiveRun::Bangs { remaining: *count }
+            },
+            ControllerMessage::RunFor { duration_ms } => {
+                if *duration_ms == 0 || *duration_ms > self.limits.max_duration_ms {
+                    return Err(RejectReason::OutOfRange);
+                }
+                ActiveRun::Until { end_ms: now_ms.wrapping_add(*duration_ms) }
+            },
+            _ => return Ok(false),
+        };
+        if self.active.is_some() {
+            return Err(RejectReason::NotAllowedWhileRunning);
+        }
+        self.bangs = 0;
+        self.active = Some(run);
+        Ok(true)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.active.is_some()
+    }
+
+    pub fn bangs(&self) -> u32 {
+        self.bangs
+    }
+
+    /// Counts a bang that has been fired
+    pub fn on_bang(&mut self) {
+        self.bangs = self.bangs.wrapping_add(1);
+        if let Some(ActiveRun::Bangs { remaining }) = &mut self.active {
+            *remaining = remaining.saturating_sub(1);
+        }
+    }
+
+    /// Ends the run early, e.g. on `Stop`, returning the completion report if one was running
+    pub fn stop(&mut self) -> Option<RemoteMessage> {
+        self.active.take().map(|_| RemoteMessage::RunComplete { bangs: self.bangs })
+    }
+
+    /// Returns the completion report once a counted run has fired all its bangs or a timed run
+    /// has used up its duration. The firmware should stop firing when this returns `Some`.
+    pub fn poll(&mut self, now_ms: u32) -> Option<RemoteMessage> {
+        let complete = match self.active? {
+            ActiveRun::Continuous => false,
+            ActiveRun::Bangs { remaining } => remaining == 0,
+            ActiveRun::Until { end_ms } => (now_ms.wrapping_sub(end_ms) as i32) >= 0,
+        };
+        if complete {
+            self.stop()
+        } else {
+            None
+        }
+    }
+}
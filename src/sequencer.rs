@@ -0,0 +1,234 @@
+use crate::{BangPhase, Envelope, ParameterSet, RampPoint, RemoteMessage, RunMode};
+
+/// What the drive should be doing after a `BangSequencer::tick`
+#[derive(Copy, Clone, Debug)]
+pub struct SequencerOutput {
+    /// `Off` between bangs and while idle
+    pub phase: BangPhase,
+    pub power: f32,
+    /// `LockFailed` or `OcdTripped` when the bang was cut short on this tick
+    pub event: Option<RemoteMessage>,
+    /// The bang ended on this tick, whether it ran its full on time or was cut short
+    pub bang_ended: bool,
+}
+
+impl SequencerOutput {
+    const OFF: Self = Self { phase: BangPhase::Off, power: 0.0, event: None, bang_ended: false };
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    Ready,
+    Bang { start_us: u32, locked: bool, power: Option<f32> },
+    Off { until_us: u32 },
+}
+
+/// Remote side: the timing of each bang, independent of the hardware. Call `tick` often (well
+/// under `LockTimeUs` apart) with the time and the measured primary current, and drive the bridge
+/// from the returned phase and power.
+///
+/// A bang starts with `StartupTimeUs` open loop at `StartupFrequencykHz`, then has `LockTimeUs`
+/// for the current to reach `MinLockCurrentA`, otherwise it's cut short with `LockFailed`.
+/// Current above `CurrentLimitA` at any point cuts it short with `OcdTripped` and ends the run.
+/// After each bang, including cut short ones, nothing fires for `OffTimeMs`. `OpenLoop` and
+/// `Interrupter` have no lock window.
+///
+/// While running, bangs repeat on their own except in `RunMode::Interrupter`, where each bang is
+/// started with `fire_at_power` from the `NotePlayer`.
+pub struct BangSequencer<'a> {
+    envelope: Envelope<'a>,
+    pending: Option<Envelope<'a>>,
+    running: bool,
+    state: State,
+}
+
+impl<'a> BangSequencer<'a> {
+    /// `ramp` is only used in `RunMode::CustomRamp`
+    pub fn new(set: &ParameterSet, ramp: &'a [RampPoint]) -> Self {
+        Self {
+            envelope: Envelope::new(set, ramp),
+            pending: None,
+            running: false,
+            state: State::Ready,
+        }
+    }
+
+    pub fn parameters(&self) -> &ParameterSet {
+        self.pending.as_ref().unwrap_or(&self.envelope).parameters()
+    }
+
+    /// Takes effect from the next bang, a bang in progress finishes with the old parameters
+    pub fn set_parameters(&mut self, set: &ParameterSet, ramp: &'a [RampPoint]) {
+        self.pending = Some(Envelope::new(set, ramp));
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn is_firing(&self) -> bool {
+        matches!(self.state, State::Bang { .. })
+    }
+
+    /// Starts firing repeatedly, the first bang as soon as the off time allows
+    pub fn run(&mut self) {
+        self.running = true;
+    }
+
+    /// Stops immediately, cutting any bang in progress short. The off time still applies to the
+    /// next bang.
+    pub fn stop(&mut self, now_us: u32) {
+        self.running = false;
+        if matches!(self.state, State::Bang { .. }) {
+            self.end_bang(now_us);
+        }
+    }
+
+    /// Starts a single bang now. Returns `false` if a bang is in progress or the off time after
+    /// the last one hasn't passed.
+    pub fn fire(&mut self, now_us: u32) -> bool {
+        self.start_bang(now_us, None)
+    }
+
+    /// Like `fire`, but drives the whole bang at `power` instead of the envelope's power
+    pub fn fire_at_power(&mut self, now_us: u32, power: f32) -> bool {
+        self.start_bang(now_us, Some(power))
+    }
+
+    fn start_bang(&mut self, now_us: u32, power: Option<f32>) -> bool {
+        if let State::Off { until_us } = self.state {
+            if (now_us.wrapping_sub(until_us) as i32) < 0 {
+                return false;
+            }
+            self.state = State::Ready;
+        }
+        if self.state != State::Ready {
+            return false;
+        }
+        if let Some(pending) = self.pending.take() {
+            self.envelope = pending;
+        }
+        self.state = State::Bang { start_us: now_us, locked: false, power };
+        true
+    }
+
+    fn end_bang(&mut self, now_us: u32) {
+        let off_time_us = self.envelope.parameters().off_time_ms as u32 * 1000;
+        self.state = State::Off { until_us: now_us.wrapping_add(off_time_us) };
+    }
+
+    pub fn tick(&mut self, now_us: u32, current_a: f32) -> SequencerOutput {
+        if self.running && self.envelope.parameters().run_mode != RunMode::Interrupter {
+            self.start_bang(now_us, None);
+        }
+        let State::Bang { start_us, locked, power } = &mut self.state else {
+            return SequencerOutput::OFF;
+        };
+        let time_us = now_us.wrapping_sub(*start_us) as f32;
+        let set = *self.envelope.parameters();
+        let phase = self.envelope.phase_at(time_us);
+        let event = if current_a > set.current_limit_a {
+            self.running = false;
+            Some(RemoteMessage::OcdTripped)
+        } else {
+            match phase {
+                BangPhase::Lock => {
+                    *locked |= current_a >= set.min_lock_current_a;
+                    None
+                },
+                BangPhase::Ramp | BangPhase::Flat if !*locked => {
+                    if set.lock_time_us == 0 || matches!(set.run_mode, RunMode::OpenLoop | RunMode::Interrupter) {
+                        *locked = true;
+                        None
+                    } else {
+                        Some(RemoteMessage::LockFailed)
+                    }
+                },
+                _ => None,
+            }
+        };
+        if event.is_some() || phase == BangPhase::Off {
+            let end_us = if event.is_some() { now_us } else { start_us.wrapping_add(set.on_time_us as u32) };
+            self.end_bang(end_us);
+            return SequencerOutput { phase: BangPhase::Off, power: 0.0, event, bang_ended: true };
+        }
+        SequencerOutput {
+            phase,
+            power: power.unwrap_or_else(|| self.envelope.power_at(time_us)),
+            event: None,
+            bang_ended: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFF_TIME_US: u32 = ParameterSet::DEFAULT.off_time_ms as u32 * 1000;
+
+    fn closed_loop() -> ParameterSet {
+        ParameterSet {
+            run_mode: RunMode::ClosedLoopRamp,
+            ..ParameterSet::DEFAULT
+        }
+    }
+
+    #[test]
+    fn lock_failure_cuts_the_bang_short_and_keeps_running() {
+        let set = closed_loop();
+        let mut sequencer = BangSequencer::new(&set, &[]);
+        sequencer.run();
+        assert_eq!(sequencer.tick(0, 0.0).phase, BangPhase::Startup);
+        assert_eq!(sequencer.tick(30, 1.0).phase, BangPhase::Lock);
+
+        // The lock window ends 70us in without the current reaching MinLockCurrentA
+        let output = sequencer.tick(80, 1.0);
+        assert!(matches!(output.event, Some(RemoteMessage::LockFailed)));
+        assert!(output.bang_ended);
+        assert_eq!(output.phase, BangPhase::Off);
+        assert!(!sequencer.is_firing());
+        assert!(sequencer.is_running());
+
+        let output = sequencer.tick(80 + OFF_TIME_US - 1, 0.0);
+        assert_eq!((output.phase, output.bang_ended), (BangPhase::Off, false));
+        assert_eq!(sequencer.tick(80 + OFF_TIME_US, 0.0).phase, BangPhase::Startup);
+    }
+
+    #[test]
+    fn locked_bang_runs_its_full_on_time() {
+        let set = closed_loop();
+        let mut sequencer = BangSequencer::new(&set, &[]);
+        sequencer.run();
+        sequencer.tick(0, 0.0);
+        sequencer.tick(30, set.min_lock_current_a);
+        let output = sequencer.tick(80, set.min_lock_current_a);
+        assert_eq!(output.phase, BangPhase::Ramp);
+        assert!(output.event.is_none());
+
+        let output = sequencer.tick(set.on_time_us as u32, 0.0);
+        assert!(output.bang_ended && output.event.is_none());
+        assert!(sequencer.is_running());
+    }
+
+    #[test]
+    fn ocd_trip_cuts_the_bang_short_and_ends_the_run() {
+        let set = closed_loop();
+        let mut sequencer = BangSequencer::new(&set, &[]);
+        sequencer.run();
+        sequencer.tick(0, 0.0);
+        sequencer.tick(30, set.min_lock_current_a);
+
+        let output = sequencer.tick(40, set.current_limit_a + 1.0);
+        assert!(matches!(output.event, Some(RemoteMessage::OcdTripped)));
+        assert!(output.bang_ended);
+        assert_eq!(output.power, 0.0);
+        assert!(!sequencer.is_running());
+
+        // Nothing fires again on its own, and a manual bang still waits out the off time
+        let output = sequencer.tick(40 + OFF_TIME_US, 0.0);
+        assert_eq!((output.phase, output.bang_ended), (BangPhase::Off, false));
+        assert!(!sequencer.fire(40 + OFF_TIME_US - 1));
+        assert!(sequencer.fire(40 + OFF_TIME_US));
+    }
+}
@@ -1,57 +1,589 @@
+use core::cell::UnsafeCell;
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Something frames can be written into: a `SerialBuffer` or its `Producer` half
+pub trait ByteSink {
+    fn free_space(&self) -> usize;
+    /// What happens to a byte pushed without room for it depends on the sink, so check
+    /// `free_space` first
+    fn push(&mut self, b: u8);
+}
+
+/// Something frames can be read from: a `SerialBuffer` or its `Consumer` half
+pub trait ByteSource {
+    fn count(&self) -> usize;
+    fn pop(&mut self) -> Option<u8>;
+    fn peek_at(&self, offset: usize) -> Option<u8>;
+
+    fn peek(&self) -> Option<u8> {
+        self.peek_at(0)
+    }
+}
+
+/// What a `SerialBuffer` does with a byte pushed while it's full. Either way the byte or the one
+/// it replaces is counted as an overflow.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drop the byte being pushed, keeping what is already buffered
+    RejectNewest,
+    /// Drop the oldest buffered byte to make room
+    OverwriteOldest,
+}
+
+// Read and write positions both count modulo 2 * N, so that a full buffer (positions N apart) can
+// be told apart from an empty one (positions equal). Only the writer stores `write` and only the
+// reader stores `read`, which is what lets the two halves of a split buffer work without locks.
 pub struct SerialBuffer<const N: usize> {
-    data: [u8; N],
-    count: usize,
-    i_write: usize,
+    data: UnsafeCell<[u8; N]>,
+    read: AtomicUsize,
+    write: AtomicUsize,
+    policy: OverflowPolicy,
+    overflows: AtomicUsize,
 }
 
+// Safe to share: through `&SerialBuffer` bytes are only read, and writes need either `&mut` or one
+// of the two halves handed out by `split`, which each own their side of the buffer.
+unsafe impl<const N: usize> Sync for SerialBuffer<N> {}
+
 impl<const N: usize> SerialBuffer<N> {
-    pub fn new() -> Self {
+    pub const fn new() -> Self {
+        Self::with_policy(OverflowPolicy::RejectNewest)
+    }
+
+    pub const fn with_policy(policy: OverflowPolicy) -> Self {
         Self {
-            data: [0u8; N],
-            count: 0,
-            i_write: 0,
+            data: UnsafeCell::new([0u8; N]),
+            read: AtomicUsize::new(0),
+            write: AtomicUsize::new(0),
+            policy,
+            overflows: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.policy = policy;
+    }
+
+    /// Bytes lost to overflow since the count was last cleared, wrapping on overflow
+    pub fn overflow_count(&self) -> usize {
+        self.overflows.load(Ordering::Relaxed)
+    }
+
+    pub fn clear_overflow_count(&mut self) {
+        self.overflows.store(0, Ordering::Relaxed);
+    }
+
+    /// Splits the buffer into a writing and a reading half, e.g. one for a UART interrupt handler
+    /// and one for the main loop. The halves only use atomic loads and stores, so this works on
+    /// cores without compare-and-swap such as the Cortex-M0. The producer can't move the read
+    /// position under the consumer, so the halves always reject the newest byte on overflow.
+    pub fn split(&mut self) -> (Producer<'_, N>, Consumer<'_, N>) {
+        (Producer { buffer: self }, Consumer { buffer: self })
+    }
+
+    fn count_between(read: usize, write: usize) -> usize {
+        (write + 2 * N - read) % (2 * N)
+    }
+
+    // Writer side only
+    fn write_byte(&self, b: u8) -> bool {
+        let write = self.write.load(Ordering::Relaxed);
+        let read = self.read.load(Ordering::Acquire);
+        if Self::count_between(read, write) == N {
+            return false;
+        }
+        // The slot at `write` is outside the readable region, so the reader never touches it
+        unsafe { self.data.get().cast::<u8>().add(write % N).write(b) };
+        self.write.store((write + 1) % (2 * N), Ordering::Release);
+        true
+    }
+
+    // Writer side only. Hands out the free space after the write position, up to `n` bytes and
+    // up to the end of the storage. The slice must be dropped before the write position moves.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn writable(&self, n: usize) -> &mut [u8] {
+        let write = self.write.load(Ordering::Relaxed);
+        let free = N - Self::count_between(self.read.load(Ordering::Acquire), write);
+        let length = n.min(free).min(N - write % N);
+        // Outside the readable region, so the reader never touches it
+        unsafe { slice::from_raw_parts_mut(self.data.get().cast::<u8>().add(write % N), length) }
+    }
+
+    // Writer side only
+    fn advance_write(&self, n: usize) {
+        let write = self.write.load(Ordering::Relaxed);
+        let free = N - Self::count_between(self.read.load(Ordering::Acquire), write);
+        let n = n.min(free).min(N - write % N);
+        self.write.store((write + n) % (2 * N), Ordering::Release);
+    }
+
+    // Writer side only
+    fn write_slice(&self, bytes: &[u8]) -> usize {
+        let mut written = 0;
+        // At most two rounds: up to the end of the storage, then from its start
+        for _ in 0..2 {
+            let chunk = unsafe { self.writable(bytes.len() - written) };
+            let length = chunk.len();
+            chunk.copy_from_slice(&bytes[written..written + length]);
+            self.advance_write(length);
+            written += length;
         }
+        written
+    }
+
+    // Writer side only, and the only place the overflow count is increased
+    fn count_overflows(&self, n: usize) {
+        let overflows = self.overflows.load(Ordering::Relaxed);
+        self.overflows.store(overflows.wrapping_add(n), Ordering::Relaxed);
     }
 
+    // Reader side only
+    fn read_byte(&self) -> Option<u8> {
+        let b = self.byte_at(0)?;
+        let read = self.read.load(Ordering::Relaxed);
+        self.read.store((read + 1) % (2 * N), Ordering::Release);
+        Some(b)
+    }
+
+    // Reader side only. Nothing in the readable region is written until the reader moves past it.
+    fn readable(&self) -> (&[u8], &[u8]) {
+        let read = self.read.load(Ordering::Relaxed);
+        let count = Self::count_between(read, self.write.load(Ordering::Acquire));
+        let first = count.min(N - read % N);
+        let data = self.data.get().cast::<u8>();
+        unsafe { (slice::from_raw_parts(data.add(read % N), first), slice::from_raw_parts(data, count - first)) }
+    }
+
+    // Reader side only
+    fn advance_read(&self, n: usize) -> usize {
+        let read = self.read.load(Ordering::Relaxed);
+        let n = n.min(Self::count_between(read, self.write.load(Ordering::Acquire)));
+        self.read.store((read + n) % (2 * N), Ordering::Release);
+        n
+    }
+
+    // Reader side only
+    fn read_slice(&self, out: &mut [u8]) -> usize {
+        let (first, second) = self.readable();
+        let from_first = first.len().min(out.len());
+        let from_second = second.len().min(out.len() - from_first);
+        out[..from_first].copy_from_slice(&first[..from_first]);
+        out[from_first..from_first + from_second].copy_from_slice(&second[..from_second]);
+        self.advance_read(from_first + from_second)
+    }
+
+    // Reader side only
+    fn byte_at(&self, offset: usize) -> Option<u8> {
+        let read = self.read.load(Ordering::Relaxed);
+        let write = self.write.load(Ordering::Acquire);
+        if offset < Self::count_between(read, write) {
+            // Inside the readable region, so the writer never touches it
+            Some(unsafe { self.data.get().cast::<u8>().add((read + offset) % N).read() })
+        } else {
+            None
+        }
+    }
+
+    /// Pushes a byte, handling a full buffer according to the overflow policy
     pub fn push(&mut self, b: u8) {
-        self.data[self.i_write] = b;
-        self.i_write += 1;
-        self.i_write %= N;
-        self.count += 1;
+        if self.write_byte(b) {
+            return;
+        }
+        self.count_overflows(1);
+        if self.policy == OverflowPolicy::OverwriteOldest {
+            self.read_byte();
+            self.write_byte(b);
+        }
+    }
+
+    /// Pushes a byte only if there is room for it. A rejected byte isn't counted as an overflow,
+    /// since it's left to the caller.
+    #[allow(clippy::result_unit_err)]
+    pub fn try_push(&mut self, b: u8) -> Result<(), ()> {
+        if self.write_byte(b) {
+            Ok(())
+        } else {
+            Err(())
+        }
     }
 
     pub fn count(&self) -> usize {
-        self.count
+        Self::count_between(self.read.load(Ordering::Acquire), self.write.load(Ordering::Acquire))
     }
 
     pub fn free_space(&self) -> usize {
-        N - self.count
+        N - self.count()
     }
 
     pub fn pop(&mut self) -> Option<u8> {
-        if self.count != 0 {
-            let index = if self.i_write < self.count {
-                self.i_write + N - self.count
-            } else {
-                self.i_write - self.count
-            };
-            self.count -= 1;
-            Some(self.data[index])
-        } else {
-            None
+        self.read_byte()
+    }
+
+    pub fn peek(&self) -> Option<u8> {
+        self.byte_at(0)
+    }
+
+    pub fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.byte_at(offset)
+    }
+
+    /// Pushes all of `bytes`, handling a full buffer according to the overflow policy
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        let mut bytes = bytes;
+        if self.policy == OverflowPolicy::OverwriteOldest {
+            if bytes.len() > N {
+                self.count_overflows(bytes.len() - N);
+                bytes = &bytes[bytes.len() - N..];
+            }
+            let needed = bytes.len().saturating_sub(self.free_space());
+            self.count_overflows(self.advance_read(needed));
+        }
+        let written = self.write_slice(bytes);
+        self.count_overflows(bytes.len() - written);
+    }
+
+    /// Pops as many bytes as fit into `out`, returning how many were read
+    pub fn read_into(&mut self, out: &mut [u8]) -> usize {
+        self.read_slice(out)
+    }
+
+    /// The buffered bytes, oldest first, as two slices since they may wrap around the end of the
+    /// storage. Either or both may be empty. Use `discard` once they have been handled.
+    pub fn as_contiguous_slices(&self) -> (&[u8], &[u8]) {
+        self.readable()
+    }
+
+    /// Drops up to `n` of the oldest bytes, returning how many were dropped
+    pub fn discard(&mut self, n: usize) -> usize {
+        self.advance_read(n)
+    }
+
+    /// Free space to write into directly, e.g. by DMA, followed by `commit`. Up to `n` bytes, but
+    /// may be shorter when the buffer is nearly full or the free space wraps around the end of the
+    /// storage.
+    pub fn reserve(&mut self, n: usize) -> &mut [u8] {
+        unsafe { self.writable(n) }
+    }
+
+    /// Makes the first `n` bytes of the last `reserve` readable
+    pub fn commit(&mut self, n: usize) {
+        self.advance_write(n);
+    }
+}
+
+impl<const N: usize> Default for SerialBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> ByteSink for SerialBuffer<N> {
+    fn free_space(&self) -> usize {
+        SerialBuffer::free_space(self)
+    }
+
+    fn push(&mut self, b: u8) {
+        SerialBuffer::push(self, b)
+    }
+}
+
+impl<const N: usize> ByteSource for SerialBuffer<N> {
+    fn count(&self) -> usize {
+        SerialBuffer::count(self)
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        SerialBuffer::pop(self)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        SerialBuffer::peek_at(self, offset)
+    }
+}
+
+/// Writing half of a split `SerialBuffer`
+pub struct Producer<'a, const N: usize> {
+    buffer: &'a SerialBuffer<N>,
+}
+
+impl<const N: usize> Producer<'_, N> {
+    /// Bytes pushed into a full buffer are dropped and counted as overflows
+    pub fn push(&mut self, b: u8) {
+        if !self.buffer.write_byte(b) {
+            self.buffer.count_overflows(1);
         }
     }
 
-    pub fn peek(&mut self) -> Option<u8> {
-        if self.count != 0 {
-            let index = if self.i_write < self.count {
-                self.i_write + N - self.count
-            } else {
-                self.i_write - self.count
-            };
-            Some(self.data[index])
+    /// Pushes as much of `bytes` as fits, the rest is dropped and counted as overflows
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        let written = self.buffer.write_slice(bytes);
+        self.buffer.count_overflows(bytes.len() - written);
+    }
+
+    /// See `SerialBuffer::reserve`
+    pub fn reserve(&mut self, n: usize) -> &mut [u8] {
+        unsafe { self.buffer.writable(n) }
+    }
+
+    pub fn commit(&mut self, n: usize) {
+        self.buffer.advance_write(n);
+    }
+
+    #[allow(clippy::result_unit_err)]
+    pub fn try_push(&mut self, b: u8) -> Result<(), ()> {
+        if self.buffer.write_byte(b) {
+            Ok(())
         } else {
-            None
+            Err(())
+        }
+    }
+
+    /// May grow at any time as the consumer reads, but never shrinks behind the producer's back
+    pub fn free_space(&self) -> usize {
+        self.buffer.free_space()
+    }
+}
+
+impl<const N: usize> ByteSink for Producer<'_, N> {
+    fn free_space(&self) -> usize {
+        Producer::free_space(self)
+    }
+
+    fn push(&mut self, b: u8) {
+        Producer::push(self, b)
+    }
+}
+
+/// Reading half of a split `SerialBuffer`
+pub struct Consumer<'a, const N: usize> {
+    buffer: &'a SerialBuffer<N>,
+}
+
+impl<const N: usize> Consumer<'_, N> {
+    /// May grow at any time as the producer writes, but never shrinks behind the consumer's back
+    pub fn count(&self) -> usize {
+        self.buffer.count()
+    }
+
+    pub fn pop(&mut self) -> Option<u8> {
+        self.buffer.read_byte()
+    }
+
+    pub fn peek(&self) -> Option<u8> {
+        self.buffer.byte_at(0)
+    }
+
+    pub fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.buffer.byte_at(offset)
+    }
+
+    pub fn read_into(&mut self, out: &mut [u8]) -> usize {
+        self.buffer.read_slice(out)
+    }
+
+    /// See `SerialBuffer::as_contiguous_slices`
+    pub fn as_contiguous_slices(&self) -> (&[u8], &[u8]) {
+        self.buffer.readable()
+    }
+
+    pub fn discard(&mut self, n: usize) -> usize {
+        self.buffer.advance_read(n)
+    }
+
+    pub fn overflow_count(&self) -> usize {
+        self.buffer.overflow_count()
+    }
+}
+
+impl<const N: usize> ByteSource for Consumer<'_, N> {
+    fn count(&self) -> usize {
+        Consumer::count(self)
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        Consumer::pop(self)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        Consumer::peek_at(self, offset)
+    }
+}
+
+// Lets the frame encoders write straight into a slice. Bytes past its end are dropped.
+pub(crate) struct SliceWriter<'a> {
+    out: &'a mut [u8],
+    position: usize,
+}
+
+impl<'a> SliceWriter<'a> {
+    pub(crate) fn new(out: &'a mut [u8]) -> Self {
+        Self { out, position: 0 }
+    }
+
+    pub(crate) fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl ByteSink for SliceWriter<'_> {
+    fn free_space(&self) -> usize {
+        self.out.len() - self.position
+    }
+
+    fn push(&mut self, b: u8) {
+        if let Some(slot) = self.out.get_mut(self.position) {
+            *slot = b;
+            self.position += 1;
         }
     }
 }
+
+// Lets the frame decoders read straight from a slice
+pub(crate) struct SliceReader<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub(crate) fn new(input: &'a [u8]) -> Self {
+        Self { input, position: 0 }
+    }
+
+    pub(crate) fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl ByteSource for SliceReader<'_> {
+    fn count(&self) -> usize {
+        self.input.len() - self.position
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        let b = *self.input.get(self.position)?;
+        self.position += 1;
+        Some(b)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.input.get(self.position + offset).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain<const N: usize>(consumer: &mut Consumer<'_, N>) -> ([u8; N], usize) {
+        let mut out = [0u8; N];
+        let length = consumer.read_into(&mut out);
+        (out, length)
+    }
+
+    #[test]
+    fn split_halves_wrap_around_the_end_of_storage() {
+        let mut buffer = SerialBuffer::<8>::new();
+        let (mut producer, mut consumer) = buffer.split();
+        // Enough rounds for both positions to pass 2 * N more than once
+        for round in 0..10u8 {
+            producer.extend_from_slice(&[round, round + 1, round + 2, round + 3, round + 4]);
+            assert_eq!(consumer.count(), 5);
+            let (out, length) = drain(&mut consumer);
+            assert_eq!(&out[..length], &[round, round + 1, round + 2, round + 3, round + 4]);
+        }
+        assert_eq!(consumer.count(), 0);
+        assert_eq!(consumer.overflow_count(), 0);
+    }
+
+    #[test]
+    fn contiguous_slices_split_at_the_end_of_storage() {
+        let mut buffer = SerialBuffer::<8>::new();
+        buffer.extend_from_slice(&[0; 6]);
+        assert_eq!(buffer.discard(5), 5);
+        buffer.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+        assert_eq!(buffer.as_contiguous_slices(), (&[0, 1, 2][..], &[3, 4, 5, 6][..]));
+        assert_eq!(buffer.peek_at(3), Some(3));
+        assert_eq!(buffer.peek_at(7), None);
+    }
+
+    #[test]
+    fn reserve_stops_at_the_end_of_storage_and_commit_continues_from_the_start() {
+        let mut buffer = SerialBuffer::<8>::new();
+        let (mut producer, mut consumer) = buffer.split();
+        producer.extend_from_slice(&[0; 6]);
+        consumer.discard(6);
+
+        let chunk = producer.reserve(5);
+        assert_eq!(chunk.len(), 2);
+        chunk.copy_from_slice(&[1, 2]);
+        producer.commit(2);
+        let chunk = producer.reserve(3);
+        assert_eq!(chunk.len(), 3);
+        chunk.copy_from_slice(&[3, 4, 5]);
+        producer.commit(3);
+
+        assert_eq!(consumer.as_contiguous_slices(), (&[1, 2][..], &[3, 4, 5][..]));
+        let (out, length) = drain(&mut consumer);
+        assert_eq!(&out[..length], &[1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn reserve_and_commit_are_limited_to_the_free_space() {
+        let mut buffer = SerialBuffer::<8>::new();
+        buffer.extend_from_slice(&[0; 5]);
+        assert_eq!(buffer.reserve(8).len(), 3);
+        buffer.commit(8);
+        assert_eq!(buffer.count(), 8);
+        assert_eq!(buffer.reserve(1).len(), 0);
+    }
+
+    #[test]
+    fn reject_newest_keeps_the_oldest_bytes_and_counts_the_rest() {
+        let mut buffer = SerialBuffer::<4>::new();
+        for b in 0..6 {
+            buffer.push(b);
+        }
+        buffer.extend_from_slice(&[6, 7]);
+        assert_eq!(buffer.overflow_count(), 4);
+        assert_eq!(buffer.as_contiguous_slices(), (&[0, 1, 2, 3][..], &[][..]));
+        assert_eq!(buffer.try_push(8), Err(()));
+        assert_eq!(buffer.overflow_count(), 4);
+        buffer.clear_overflow_count();
+        assert_eq!(buffer.overflow_count(), 0);
+    }
+
+    #[test]
+    fn overwrite_oldest_keeps_the_newest_bytes_and_counts_the_rest() {
+        let mut buffer = SerialBuffer::<4>::with_policy(OverflowPolicy::OverwriteOldest);
+        for b in 0..6 {
+            buffer.push(b);
+        }
+        assert_eq!(buffer.overflow_count(), 2);
+        let (first, second) = buffer.as_contiguous_slices();
+        assert_eq!((first.len() + second.len(), buffer.peek()), (4, Some(2)));
+
+        buffer.extend_from_slice(&[10, 11, 12, 13, 14, 15]);
+        assert_eq!(buffer.overflow_count(), 8);
+        let mut out = [0u8; 4];
+        assert_eq!(buffer.read_into(&mut out), 4);
+        assert_eq!(out, [12, 13, 14, 15]);
+    }
+
+    #[test]
+    fn producer_counts_overflows_seen_by_the_consumer() {
+        let mut buffer = SerialBuffer::<4>::with_policy(OverflowPolicy::OverwriteOldest);
+        let (mut producer, mut consumer) = buffer.split();
+        producer.extend_from_slice(&[0, 1, 2]);
+        producer.push(3);
+        producer.push(4);
+        producer.extend_from_slice(&[5, 6]);
+        assert_eq!(consumer.overflow_count(), 3);
+        let (out, length) = drain(&mut consumer);
+        assert_eq!(&out[..length], &[0, 1, 2, 3]);
+    }
+}
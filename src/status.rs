@@ -0,0 +1,159 @@
+use crate::{ByteSink, RemoteMessage, RunMode};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RunState {
+    Idle,
+    Armed,
+    Ramping,
+    Running,
+    Faulted,
+}
+
+const RUN_STATE_ID_IDLE    : u8 = 0;
+const RUN_STATE_ID_ARMED   : u8 = 1;
+const RUN_STATE_ID_RAMPING : u8 = 2;
+const RUN_STATE_ID_RUNNING : u8 = 3;
+const RUN_STATE_ID_FAULTED : u8 = 4;
+
+#[allow(clippy::from_over_into)]
+impl Into<u8> for RunState {
+    fn into(self) -> u8 {
+        match self {
+            Self::Idle    => RUN_STATE_ID_IDLE,
+            Self::Armed   => RUN_STATE_ID_ARMED,
+            Self::Ramping => RUN_STATE_ID_RAMPING,
+            Self::Running => RUN_STATE_ID_RUNNING,
+            Self::Faulted => RUN_STATE_ID_FAULTED,
+        }
+    }
+}
+
+impl TryFrom<u8> for RunState {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, ()> {
+        Ok(match value {
+            RUN_STATE_ID_IDLE    => Self::Idle,
+            RUN_STATE_ID_ARMED   => Self::Armed,
+            RUN_STATE_ID_RAMPING => Self::Ramping,
+            RUN_STATE_ID_RUNNING => Self::Running,
+            RUN_STATE_ID_FAULTED => Self::Faulted,
+            _ => return Err(())
+        })
+    }
+}
+
+/// Latched fault bits reported in a status message. Only the low 7 bits go over the wire.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FaultFlags(pub u8);
+
+impl FaultFlags {
+    pub const NONE               : Self = Self(0x00);
+    pub const LOCK_FAILED        : Self = Self(0x01);
+    pub const OCD_TRIPPED        : Self = Self(0x02);
+    pub const KEEP_ALIVE_TIMEOUT : Self = Self(0x04);
+    pub const EMERGENCY_STOP     : Self = Self(0x08);
+
+    pub fn contains(&self, flags: Self) -> bool {
+        (self.0 & flags.0) == flags.0
+    }
+
+    pub fn insert(&mut self, flags: Self) {
+        self.0 |= flags.0;
+    }
+
+    pub fn remove(&mut self, flags: Self) {
+        self.0 &= !flags.0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RemoteStatus {
+    pub run_state: RunState,
+    pub run_mode: RunMode,
+    /// 0..=127, a `Status` with a larger preset isn't sent
+    pub preset: u8,
+    pub faults: FaultFlags,
+    pub bang_count: u32,
+}
+
+/// Remote side: sends a `RemoteMessage::Status` whenever the status changes, and at least every
+/// `interval_ms` otherwise so the controller can tell the link is alive.
+pub struct StatusBroadcaster {
+    interval_ms: u32,
+    last_sent: Option<RemoteStatus>,
+    last_sent_ms: u32,
+}
+
+impl StatusBroadcaster {
+    pub fn new(interval_ms: u32) -> Self {
+        Self {
+            interval_ms,
+            last_sent: None,
+            last_sent_ms: 0,
+        }
+    }
+
+    pub fn poll(&mut self, now_ms: u32, status: &RemoteStatus, tx_buffer: &mut impl ByteSink) -> bool {
+        let due = match self.last_sent {
+            Some(last_sent) => last_sent != *status || now_ms.wrapping_sub(self.last_sent_ms) >= self.interval_ms,
+            None => true,
+        };
+        if due && RemoteMessage::Status(*status).try_send(tx_buffer) {
+            self.last_sent = Some(*status);
+            self.last_sent_ms = now_ms;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Controller side: keeps the most recent status reported by the remote and when it arrived.
+pub struct StatusMonitor {
+    latest: Option<RemoteStatus>,
+    received_ms: u32,
+}
+
+impl StatusMonitor {
+    pub fn new() -> Self {
+        Self {
+            latest: None,
+            received_ms: 0,
+        }
+    }
+
+    pub fn update(&mut self, message: &RemoteMessage, now_ms: u32) -> bool {
+        if let RemoteMessage::Status(status) = message {
+            self.latest = Some(*status);
+            self.received_ms = now_ms;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn latest(&self) -> Option<&RemoteStatus> {
+        self.latest.as_ref()
+    }
+
+    pub fn age_ms(&self, now_ms: u32) -> Option<u32> {
+        self.latest.map(|_| now_ms.wrapping_sub(self.received_ms))
+    }
+
+    pub fn is_stale(&self, now_ms: u32, timeout_ms: u32) -> bool {
+        match self.age_ms(now_ms) {
+            Some(age_ms) => age_ms > timeout_ms,
+            None => true,
+        }
+    }
+}
+
+impl Default for StatusMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
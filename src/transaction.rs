@@ -0,0 +1,76 @@
+use crate::{PARAMETER_COUNT, ParameterSet, ParameterValue, RejectReason};
+
+/// Remote side staging area for a `BeginTransaction` .. `Commit` sequence. Staged values are only
+/// applied on commit, and only if the resulting full parameter set passes validation.
+pub struct Transaction {
+    open: bool,
+    staged: [Option<ParameterValue>; PARAMETER_COUNT],
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            staged: [None; PARAMETER_COUNT],
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Starts a new transaction, discarding anything staged by a previous one
+    pub fn begin(&mut self) {
+        self.open = true;
+        self.staged = [None; PARAMETER_COUNT];
+    }
+
+    /// Stages a value, replacing any earlier value staged for the same parameter
+    pub fn stage(&mut self, value: ParameterValue) -> Result<(), RejectReason> {
+        if !self.open {
+            return Err(RejectReason::NoTransaction);
+        }
+        self.staged[value.parameter().index()] = Some(value);
+        Ok(())
+    }
+
+    pub fn staged(&self) -> impl Iterator<Item = ParameterValue> + '_ {
+        self.staged.iter().filter_map(|value| *value)
+    }
+
+    pub fn abort(&mut self) -> Result<(), RejectReason> {
+        if !self.open {
+            return Err(RejectReason::NoTransaction);
+        }
+        self.open = false;
+        self.staged = [None; PARAMETER_COUNT];
+        Ok(())
+    }
+
+    /// Applies the staged values on top of `current` and checks the result with `validate`. The
+    /// transaction is closed either way; on success the new full set is returned for the firmware
+    /// to apply in one go.
+    pub fn commit(
+        &mut self,
+        current: &ParameterSet,
+        validate: impl FnOnce(&ParameterSet) -> Result<(), RejectReason>,
+    ) -> Result<ParameterSet, RejectReason> {
+        if !self.open {
+            return Err(RejectReason::NoTransaction);
+        }
+        let mut result = *current;
+        for value in self.staged() {
+            result.set(value);
+        }
+        self.open = false;
+        self.staged = [None; PARAMETER_COUNT];
+        validate(&result)?;
+        Ok(result)
+    }
+}
+
+impl Default for Transaction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,111 @@
+#[cfg(feature = "embedded-hal-nb")]
+use embedded_hal_nb::{nb, serial};
+#[cfg(feature = "embedded-io-async")]
+use embedded_io_async::ReadExactError;
+
+use crate::{DecodeError, Decoder, MAX_FRAME_LENGTH, Message};
+
+/// Writes a whole frame to an `embedded_io` writer, blocking until it's all written
+#[cfg(feature = "embedded-io")]
+pub fn send<W: embedded_io::Write>(uart: &mut W, message: &impl Message) -> Result<(), W::Error> {
+    let mut frame = [0u8; MAX_FRAME_LENGTH];
+    let length = message.encode(&mut frame).unwrap_or(0);
+    uart.write_all(&frame[..length])
+}
+
+/// Writes a whole frame to an `embedded_hal_nb` serial port, blocking until it's all written
+#[cfg(feature = "embedded-hal-nb")]
+pub fn send_nb<W: serial::Write<u8>>(uart: &mut W, message: &impl Message) -> Result<(), W::Error> {
+    let mut frame = [0u8; MAX_FRAME_LENGTH];
+    let length = message.encode(&mut frame).unwrap_or(0);
+    for byte in &frame[..length] {
+        nb::block!(uart.write(*byte))?;
+    }
+    Ok(())
+}
+
+/// Writes a whole frame to an `embedded_io_async` writer. Dropping the future part way through
+/// leaves a partial frame on the wire, which the receiving `Decoder` drops at the next start byte.
+#[cfg(feature = "embedded-io-async")]
+pub async fn send_async<W: embedded_io_async::Write>(uart: &mut W, message: &impl Message) -> Result<(), W::Error> {
+    let mut frame = [0u8; MAX_FRAME_LENGTH];
+    let length = message.encode(&mut frame).unwrap_or(0);
+    uart.write_all(&frame[..length]).await
+}
+
+/// Pulls bytes from a non-blocking UART through a `Decoder`. Poll it from the main loop; it reads
+/// only what the UART already has, stopping at the first complete frame, so a partial frame
+/// carries over to the next poll.
+pub struct Receiver<M: Message> {
+    decoder: Decoder<M>,
+}
+
+impl<M: Message> Receiver<M> {
+    pub const fn new() -> Self {
+        Self {
+            decoder: Decoder::new(),
+        }
+    }
+
+    pub fn decoder(&mut self) -> &mut Decoder<M> {
+        &mut self.decoder
+    }
+
+    /// Reads from an `embedded_io` reader while it has data ready. Returns `None` once it runs
+    /// out without completing a frame.
+    #[cfg(feature = "embedded-io")]
+    pub fn poll<R>(&mut self, uart: &mut R) -> Result<Option<Result<M, DecodeError>>, R::Error>
+    where
+        R: embedded_io::Read + embedded_io::ReadReady,
+    {
+        let mut byte = [0u8];
+        while uart.read_ready()? {
+            if uart.read(&mut byte)? == 0 {
+                break;
+            }
+            if let Some(result) = self.decoder.feed(byte[0]) {
+                return Ok(Some(result));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads from an `embedded_hal_nb` serial port until it would block, which is passed on as
+    /// `nb::Error::WouldBlock`
+    #[cfg(feature = "embedded-hal-nb")]
+    pub fn poll_nb<R: serial::Read<u8>>(&mut self, uart: &mut R) -> nb::Result<Result<M, DecodeError>, R::Error> {
+        loop {
+            let byte = uart.read()?;
+            if let Some(result) = self.decoder.feed(byte) {
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Waits for the next complete frame from an `embedded_io_async` reader.
+    ///
+    /// Cancel safe as long as the reader's `read` is: bytes are read one at a time and go
+    /// straight into the decoder, so dropping the future, e.g. when it loses a `select` against a
+    /// timeout, keeps any partial frame for the next call.
+    #[cfg(feature = "embedded-io-async")]
+    pub async fn recv<R>(&mut self, uart: &mut R) -> Result<Result<M, DecodeError>, ReadExactError<R::Error>>
+    where
+        R: embedded_io_async::Read,
+    {
+        let mut byte = [0u8];
+        loop {
+            if uart.read(&mut byte).await? == 0 {
+                return Err(ReadExactError::UnexpectedEof);
+            }
+            if let Some(result) = self.decoder.feed(byte[0]) {
+                return Ok(result);
+            }
+        }
+    }
+}
+
+impl<M: Message> Default for Receiver<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}